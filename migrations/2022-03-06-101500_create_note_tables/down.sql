@@ -0,0 +1,2 @@
+DROP TABLE note_content;
+DROP TABLE note;
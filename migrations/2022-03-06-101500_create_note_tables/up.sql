@@ -0,0 +1,18 @@
+CREATE TABLE IF NOT EXISTS note (
+    user_id TEXT NOT NULL REFERENCES user_account (user_id) ON DELETE CASCADE ON UPDATE CASCADE,
+    note_id TEXT NOT NULL,
+    date_time_created TIMESTAMP NOT NULL DEFAULT NOW(),
+    date_time_last_edited TIMESTAMP NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, note_id)
+);
+
+CREATE TABLE IF NOT EXISTS note_content (
+    user_id TEXT NOT NULL,
+    note_id TEXT NOT NULL,
+    note_content_id TEXT NOT NULL,
+    order_number INTEGER NOT NULL,
+    content_type TEXT NOT NULL,
+    content TEXT NOT NULL,
+    PRIMARY KEY (user_id, note_id, note_content_id),
+    FOREIGN KEY (user_id, note_id) REFERENCES note (user_id, note_id) ON DELETE CASCADE ON UPDATE CASCADE
+);
@@ -0,0 +1,3 @@
+DROP TABLE consumed_refresh_token;
+
+ALTER TABLE user_account DROP COLUMN token_family_id;
@@ -0,0 +1,10 @@
+-- Every sign in starts new token family, that is kept through refresh token rotations
+ALTER TABLE user_account ADD COLUMN token_family_id TEXT NOT NULL DEFAULT md5(random()::text);
+ALTER TABLE user_account ALTER COLUMN token_family_id DROP DEFAULT;
+
+CREATE TABLE IF NOT EXISTS consumed_refresh_token (
+    refresh_token TEXT NOT NULL PRIMARY KEY,
+    user_id TEXT NOT NULL REFERENCES user_account (user_id) ON DELETE CASCADE,
+    token_family_id TEXT NOT NULL,
+    consumed_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,20 @@
+-- Only refresh token hashes are stored in sessions, so raw tokens can't be restored
+-- and every user will have to sign in again
+ALTER TABLE user_account ADD COLUMN access_token TEXT NOT NULL UNIQUE DEFAULT md5(random()::text);
+ALTER TABLE user_account ADD COLUMN refresh_token TEXT NOT NULL UNIQUE DEFAULT md5(random()::text);
+ALTER TABLE user_account ADD COLUMN token_family_id TEXT NOT NULL DEFAULT md5(random()::text);
+ALTER TABLE user_account ALTER COLUMN access_token DROP DEFAULT;
+ALTER TABLE user_account ALTER COLUMN refresh_token DROP DEFAULT;
+ALTER TABLE user_account ALTER COLUMN token_family_id DROP DEFAULT;
+
+CREATE TABLE IF NOT EXISTS user_account_refresh_token (
+    refresh_token TEXT NOT NULL PRIMARY KEY,
+    user_id TEXT NOT NULL REFERENCES user_account (user_id) ON DELETE CASCADE,
+    token_family_id TEXT NOT NULL,
+    consumed_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+DROP TABLE consumed_refresh_token;
+ALTER TABLE user_account_refresh_token RENAME TO consumed_refresh_token;
+
+DROP TABLE session;
@@ -0,0 +1,33 @@
+CREATE TABLE IF NOT EXISTS session (
+    session_id TEXT NOT NULL PRIMARY KEY,
+    user_id TEXT NOT NULL REFERENCES user_account (user_id) ON DELETE CASCADE,
+    device_name TEXT,
+    user_agent TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    last_used_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    access_token TEXT NOT NULL UNIQUE,
+    refresh_token_hash TEXT NOT NULL UNIQUE
+);
+
+-- Every token family becomes a session
+INSERT INTO session (session_id, user_id, access_token, refresh_token_hash)
+SELECT token_family_id, user_id, access_token, encode(sha256(convert_to(refresh_token, 'UTF8')), 'hex')
+FROM user_account;
+
+CREATE TABLE IF NOT EXISTS consumed_session_refresh_token (
+    refresh_token_hash TEXT NOT NULL PRIMARY KEY,
+    session_id TEXT NOT NULL REFERENCES session (session_id) ON DELETE CASCADE,
+    consumed_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+INSERT INTO consumed_session_refresh_token (refresh_token_hash, session_id, consumed_at)
+SELECT encode(sha256(convert_to(refresh_token, 'UTF8')), 'hex'), token_family_id, consumed_at
+FROM consumed_refresh_token
+WHERE token_family_id IN (SELECT session_id FROM session);
+
+DROP TABLE consumed_refresh_token;
+ALTER TABLE consumed_session_refresh_token RENAME TO consumed_refresh_token;
+
+ALTER TABLE user_account DROP COLUMN access_token;
+ALTER TABLE user_account DROP COLUMN refresh_token;
+ALTER TABLE user_account DROP COLUMN token_family_id;
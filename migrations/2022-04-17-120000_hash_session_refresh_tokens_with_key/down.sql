@@ -0,0 +1,12 @@
+DELETE FROM session;
+
+DROP TABLE consumed_refresh_token;
+
+CREATE TABLE IF NOT EXISTS consumed_refresh_token (
+    refresh_token_hash TEXT NOT NULL PRIMARY KEY,
+    session_id TEXT NOT NULL REFERENCES session (session_id) ON DELETE CASCADE,
+    consumed_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+ALTER TABLE session DROP COLUMN refresh_token_id;
+ALTER TABLE session ADD COLUMN access_token TEXT NOT NULL UNIQUE;
@@ -0,0 +1,14 @@
+-- Stored access tokens and unkeyed refresh token hashes can't be converted to keyed hashes,
+-- so existing sessions are invalidated and everybody has to sign in again
+DELETE FROM session;
+
+ALTER TABLE session DROP COLUMN access_token;
+ALTER TABLE session ADD COLUMN refresh_token_id TEXT NOT NULL UNIQUE;
+
+DROP TABLE consumed_refresh_token;
+
+CREATE TABLE IF NOT EXISTS consumed_refresh_token (
+    refresh_token_id TEXT NOT NULL PRIMARY KEY,
+    session_id TEXT NOT NULL REFERENCES session (session_id) ON DELETE CASCADE,
+    consumed_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,2 @@
+DROP INDEX user_account_user_name_lower_key;
+ALTER TABLE user_account ADD CONSTRAINT user_account_user_name_key UNIQUE (user_name);
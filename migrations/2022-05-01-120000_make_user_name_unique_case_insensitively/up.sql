@@ -0,0 +1,19 @@
+-- User names, that differ only in case, are resolved here, so that unique index can be created:
+-- the oldest account keeps its name, others get suffix of their user id, which keeps names valid.
+-- Renamed accounts are those, which names end with '-' and 8 characters of their user id
+UPDATE user_account
+SET user_name = LEFT(user_name, 23) || '-' || LEFT(REPLACE(user_id, '-', ''), 8)
+WHERE user_id IN (
+    SELECT user_id
+    FROM (
+        SELECT
+            user_id,
+            ROW_NUMBER() OVER (PARTITION BY LOWER(user_name) ORDER BY created_at, user_id) AS name_rank
+        FROM user_account
+        WHERE user_name IS NOT NULL
+    ) AS ranked_user_account
+    WHERE name_rank > 1
+);
+
+ALTER TABLE user_account DROP CONSTRAINT user_account_user_name_key;
+CREATE UNIQUE INDEX user_account_user_name_lower_key ON user_account (LOWER(user_name));
@@ -0,0 +1,5 @@
+DROP TABLE account_token;
+
+DROP INDEX user_account_email_lower_key;
+ALTER TABLE user_account DROP COLUMN is_email_verified;
+ALTER TABLE user_account DROP COLUMN email;
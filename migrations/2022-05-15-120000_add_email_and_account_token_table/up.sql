@@ -0,0 +1,15 @@
+ALTER TABLE user_account ADD COLUMN email VARCHAR(254);
+ALTER TABLE user_account ADD COLUMN is_email_verified BOOLEAN NOT NULL DEFAULT FALSE;
+-- Only verified addresses are unique, so that nobody can claim somebody else's address before it's verified
+CREATE UNIQUE INDEX user_account_email_lower_key ON user_account (LOWER(email)) WHERE is_email_verified;
+
+-- Single-use tokens, that are sent by mail, only their hashes are stored
+CREATE TABLE IF NOT EXISTS account_token (
+    token_hash TEXT NOT NULL PRIMARY KEY,
+    user_id TEXT NOT NULL REFERENCES user_account (user_id) ON DELETE CASCADE ON UPDATE CASCADE,
+    purpose TEXT NOT NULL,
+    -- Email address, that token was sent to
+    email VARCHAR(254) NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMP NOT NULL
+);
@@ -0,0 +1,5 @@
+DROP TABLE recovery_code;
+
+ALTER TABLE user_account DROP COLUMN totp_last_used_step;
+ALTER TABLE user_account DROP COLUMN is_totp_enabled;
+ALTER TABLE user_account DROP COLUMN totp_secret;
@@ -0,0 +1,11 @@
+-- Secret is stored once enrollment starts, but it's required on sign in only after it's confirmed
+ALTER TABLE user_account ADD COLUMN totp_secret TEXT;
+ALTER TABLE user_account ADD COLUMN is_totp_enabled BOOLEAN NOT NULL DEFAULT FALSE;
+-- Time step of the last accepted code, so that every code can be used only once
+ALTER TABLE user_account ADD COLUMN totp_last_used_step BIGINT;
+
+CREATE TABLE IF NOT EXISTS recovery_code (
+    recovery_code_id TEXT NOT NULL PRIMARY KEY,
+    user_id TEXT NOT NULL REFERENCES user_account (user_id) ON DELETE CASCADE ON UPDATE CASCADE,
+    code_hash TEXT NOT NULL
+);
@@ -1,87 +1,449 @@
 use super::{
-    account_dto::{CredentialsDto, SignUpDataDto},
+    account_dto::{
+        AccountTokenDto, ChangeEmailDto, ChangePasswordDto, ConfirmTotpEnrollmentDto,
+        CredentialsDto, DisableTotpDto, EmailDto, PasswordDto, ResetPasswordDto, SecondFactorDto,
+        SignInDataDto, SignUpDataDto, UserNameDto, UserNameQueryDto,
+    },
     account_interaction,
+    error_data::Error,
+    middleware,
     postgres_database_connection::PostgresDatabaseConnectionPool,
+    security_data::AuthenticatedUser,
 };
 use actix_web::{
-    guard::{Delete, Post},
-    web::{resource, scope, Data, Json},
-    HttpRequest, Responder, Scope,
+    dev::{HttpServiceFactory, RequestHead},
+    guard::{fn_guard, Delete, Get, Post, Put},
+    web::{resource, scope, Data, Json, Path, Query},
+    HttpRequest, HttpResponse, Responder, Scope,
 };
+use actix_web_httpauth::middleware::HttpAuthentication;
 
 pub const ACCOUNT_PATH: &str = "/v1/account";
 pub const SIGN_UP_PATH: &str = "/signUp";
 pub const SIGN_IN_PATH: &str = "/signIn";
+pub const SIGN_IN_SECOND_FACTOR_PATH: &str = "/signIn/mfa";
+pub const SIGN_OUT_PATH: &str = "/signOut";
 pub const DELETE_ACCOUNT_PATH: &str = "";
 pub const REFRESH_TOKEN_PATH: &str = "/refreshToken";
+pub const UPGRADE_GUEST_PATH: &str = "/upgrade";
+pub const USER_NAME_PATH: &str = "/userName";
+pub const USER_NAME_AVAILABILITY_PATH: &str = "/userNameAvailability";
+pub const EMAIL_PATH: &str = "/email";
+pub const EMAIL_VERIFICATION_PATH: &str = "/email/verification";
+pub const PASSWORD_PATH: &str = "/password";
+pub const PASSWORD_RESET_PATH: &str = "/password/reset";
+pub const PASSWORD_RESET_CONFIRMATION_PATH: &str = "/password/reset/confirmation";
+pub const TOTP_PATH: &str = "/totp";
+pub const TOTP_CONFIRMATION_PATH: &str = "/totp/confirmation";
+pub const SESSIONS_PATH: &str = "/sessions";
+pub const SESSION_PATH: &str = "/sessions/{session_id}";
+
+// Public paths are matched by public scope, everything else falls through to private one
+const PUBLIC_PATHS: [&str; 8] = [
+    SIGN_UP_PATH,
+    SIGN_IN_PATH,
+    SIGN_IN_SECOND_FACTOR_PATH,
+    REFRESH_TOKEN_PATH,
+    USER_NAME_AVAILABILITY_PATH,
+    EMAIL_VERIFICATION_PATH,
+    PASSWORD_RESET_PATH,
+    PASSWORD_RESET_CONFIRMATION_PATH,
+];
 
 // tbd make all routes consts
 pub fn account_v1_scope() -> Scope {
-    let sign_up_service_factory = resource(SIGN_UP_PATH).guard(Post()).to(sign_up);
-    let sign_in_service_factory = resource(SIGN_IN_PATH).guard(Post()).to(sign_in);
-    let delete_account_service_factory = resource(DELETE_ACCOUNT_PATH)
-        .guard(Delete())
-        .to(delete_account);
+    scope(ACCOUNT_PATH)
+        .service(account_v1_public_scope())
+        .service(account_v1_private_scope())
+}
+
+fn account_v1_public_scope() -> Scope {
+    // Guests may call these with access token, so it's verified, when present
+    let sign_up_service_factory = resource(SIGN_UP_PATH)
+        .guard(Post())
+        .wrap(HttpAuthentication::with_fn(
+            middleware::optional_bearer_auth_validator,
+        ))
+        .to(sign_up);
+    let sign_in_service_factory = resource(SIGN_IN_PATH)
+        .guard(Post())
+        .wrap(HttpAuthentication::with_fn(
+            middleware::optional_bearer_auth_validator,
+        ))
+        .to(sign_in);
+    let sign_in_with_second_factor_service_factory = resource(SIGN_IN_SECOND_FACTOR_PATH)
+        .guard(Post())
+        .wrap(HttpAuthentication::with_fn(
+            middleware::optional_bearer_auth_validator,
+        ))
+        .to(sign_in_with_second_factor);
+    // Refresh token is sent in body and authenticates request by itself
     let refresh_token_service_factory =
         resource(REFRESH_TOKEN_PATH).guard(Post()).to(refresh_token);
-    scope(ACCOUNT_PATH)
+    // Used by sign up form before user has any tokens
+    let user_name_availability_service_factory = resource(USER_NAME_AVAILABILITY_PATH)
+        .guard(Get())
+        .to(check_user_name_availability);
+    // Mailed tokens authenticate these requests by themselves
+    let verify_email_service_factory = resource(EMAIL_VERIFICATION_PATH)
+        .guard(Post())
+        .to(verify_email);
+    let request_password_reset_service_factory = resource(PASSWORD_RESET_PATH)
+        .guard(Post())
+        .to(request_password_reset);
+    let reset_password_service_factory = resource(PASSWORD_RESET_CONFIRMATION_PATH)
+        .guard(Post())
+        .to(reset_password);
+    scope("")
+        .guard(fn_guard(is_public_path))
         .service(sign_up_service_factory)
         .service(sign_in_service_factory)
-        .service(delete_account_service_factory)
+        .service(sign_in_with_second_factor_service_factory)
         .service(refresh_token_service_factory)
+        .service(user_name_availability_service_factory)
+        .service(verify_email_service_factory)
+        .service(request_password_reset_service_factory)
+        .service(reset_password_service_factory)
+}
+
+fn account_v1_private_scope() -> impl HttpServiceFactory {
+    let sign_out_service_factory = resource(SIGN_OUT_PATH).guard(Post()).to(sign_out);
+    let delete_account_service_factory = resource(DELETE_ACCOUNT_PATH)
+        .guard(Delete())
+        .to(delete_account);
+    let upgrade_guest_service_factory =
+        resource(UPGRADE_GUEST_PATH).guard(Post()).to(upgrade_guest);
+    let change_user_name_service_factory =
+        resource(USER_NAME_PATH).guard(Put()).to(change_user_name);
+    let change_password_service_factory = resource(PASSWORD_PATH).guard(Post()).to(change_password);
+    let change_email_service_factory = resource(EMAIL_PATH).guard(Put()).to(change_email);
+    let start_totp_enrollment_service_factory =
+        resource(TOTP_PATH).guard(Post()).to(start_totp_enrollment);
+    let confirm_totp_enrollment_service_factory = resource(TOTP_CONFIRMATION_PATH)
+        .guard(Post())
+        .to(confirm_totp_enrollment);
+    let disable_totp_service_factory = resource(TOTP_PATH).guard(Delete()).to(disable_totp);
+    let get_sessions_service_factory = resource(SESSIONS_PATH).guard(Get()).to(get_sessions);
+    let delete_other_sessions_service_factory = resource(SESSIONS_PATH)
+        .guard(Delete())
+        .to(delete_other_sessions);
+    let delete_session_service_factory = resource(SESSION_PATH).guard(Delete()).to(delete_session);
+    scope("")
+        .wrap(HttpAuthentication::with_fn(
+            middleware::bearer_auth_validator,
+        ))
+        .service(sign_out_service_factory)
+        .service(delete_account_service_factory)
+        .service(upgrade_guest_service_factory)
+        .service(change_user_name_service_factory)
+        .service(change_password_service_factory)
+        .service(change_email_service_factory)
+        .service(start_totp_enrollment_service_factory)
+        .service(confirm_totp_enrollment_service_factory)
+        .service(disable_totp_service_factory)
+        .service(get_sessions_service_factory)
+        .service(delete_other_sessions_service_factory)
+        .service(delete_session_service_factory)
+}
+
+fn is_public_path(request_head: &RequestHead) -> bool {
+    request_head
+        .uri
+        .path()
+        .strip_prefix(ACCOUNT_PATH)
+        .is_some_and(|path| PUBLIC_PATHS.contains(&path))
 }
 
 async fn sign_up(
     request: HttpRequest,
     database_connection_pool: Data<PostgresDatabaseConnectionPool>,
+    authenticated_user: Option<AuthenticatedUser>,
     sign_up_data_dto: Json<SignUpDataDto>,
-) -> impl Responder {
+) -> Result<impl Responder, Error> {
     let profile_data_dto = account_interaction::sign_up(
         request,
         database_connection_pool.into_inner(),
+        authenticated_user,
         sign_up_data_dto.into_inner(),
     )
-    .await;
+    .await?;
 
-    Json(profile_data_dto)
+    Ok(Json(profile_data_dto))
 }
 
 async fn sign_in(
     request: HttpRequest,
     database_connection_pool: Data<PostgresDatabaseConnectionPool>,
-    credentials_dto: Json<CredentialsDto>,
-) -> impl Responder {
-    let profile_data_dto = account_interaction::sign_in(
+    authenticated_user: Option<AuthenticatedUser>,
+    sign_in_data_dto: Json<SignInDataDto>,
+) -> Result<impl Responder, Error> {
+    let sign_in_result_dto = account_interaction::sign_in(
         request,
         database_connection_pool.into_inner(),
-        credentials_dto.into_inner(),
+        authenticated_user,
+        sign_in_data_dto.into_inner(),
+    )
+    .await?;
+
+    Ok(Json(sign_in_result_dto))
+}
+
+async fn sign_in_with_second_factor(
+    request: HttpRequest,
+    database_connection_pool: Data<PostgresDatabaseConnectionPool>,
+    authenticated_user: Option<AuthenticatedUser>,
+    second_factor_dto: Json<SecondFactorDto>,
+) -> Result<impl Responder, Error> {
+    let profile_dto = account_interaction::sign_in_with_second_factor(
+        request,
+        database_connection_pool.into_inner(),
+        authenticated_user,
+        second_factor_dto.into_inner(),
     )
-    .await;
+    .await?;
+
+    Ok(Json(profile_dto))
+}
+
+async fn sign_out(
+    database_connection_pool: Data<PostgresDatabaseConnectionPool>,
+    authenticated_user: AuthenticatedUser,
+) -> Result<impl Responder, Error> {
+    account_interaction::sign_out(database_connection_pool.into_inner(), authenticated_user)
+        .await?;
 
-    Json(profile_data_dto)
+    Ok(HttpResponse::Ok())
 }
 
 async fn delete_account(
     request: HttpRequest,
     database_connection_pool: Data<PostgresDatabaseConnectionPool>,
-) -> impl Responder {
-    let profile_dto =
-        account_interaction::delete_account(request, database_connection_pool.into_inner()).await;
+    authenticated_user: AuthenticatedUser,
+) -> Result<impl Responder, Error> {
+    let profile_dto = account_interaction::delete_account(
+        request,
+        database_connection_pool.into_inner(),
+        authenticated_user,
+    )
+    .await?;
 
-    Json(profile_dto)
+    Ok(Json(profile_dto))
 }
 
 async fn refresh_token(
-    request: HttpRequest,
     database_connection_pool: Data<PostgresDatabaseConnectionPool>,
     refresh_token: Json<String>,
-) -> impl Responder {
+) -> Result<impl Responder, Error> {
     let auth_token_dto = account_interaction::refresh_token(
-        request,
         database_connection_pool.into_inner(),
         refresh_token.into_inner(),
     )
-    .await;
+    .await?;
+
+    Ok(Json(auth_token_dto))
+}
+
+async fn upgrade_guest(
+    request: HttpRequest,
+    database_connection_pool: Data<PostgresDatabaseConnectionPool>,
+    authenticated_user: AuthenticatedUser,
+    credentials_dto: Json<CredentialsDto>,
+) -> Result<impl Responder, Error> {
+    let profile_dto = account_interaction::upgrade_guest(
+        request,
+        database_connection_pool.into_inner(),
+        authenticated_user,
+        credentials_dto.into_inner(),
+    )
+    .await?;
+
+    Ok(Json(profile_dto))
+}
+
+async fn check_user_name_availability(
+    request: HttpRequest,
+    database_connection_pool: Data<PostgresDatabaseConnectionPool>,
+    user_name_query_dto: Query<UserNameQueryDto>,
+) -> Result<impl Responder, Error> {
+    let user_name_availability_dto = account_interaction::check_user_name_availability(
+        request,
+        database_connection_pool.into_inner(),
+        user_name_query_dto.into_inner().user_name,
+    )
+    .await?;
+
+    Ok(Json(user_name_availability_dto))
+}
+
+async fn change_user_name(
+    database_connection_pool: Data<PostgresDatabaseConnectionPool>,
+    authenticated_user: AuthenticatedUser,
+    user_name_dto: Json<UserNameDto>,
+) -> Result<impl Responder, Error> {
+    let user_name_dto = account_interaction::change_user_name(
+        database_connection_pool.into_inner(),
+        authenticated_user,
+        user_name_dto.into_inner(),
+    )
+    .await?;
+
+    Ok(Json(user_name_dto))
+}
+
+async fn change_password(
+    database_connection_pool: Data<PostgresDatabaseConnectionPool>,
+    authenticated_user: AuthenticatedUser,
+    change_password_dto: Json<ChangePasswordDto>,
+) -> Result<impl Responder, Error> {
+    account_interaction::change_password(
+        database_connection_pool.into_inner(),
+        authenticated_user,
+        change_password_dto.into_inner(),
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok())
+}
+
+async fn change_email(
+    database_connection_pool: Data<PostgresDatabaseConnectionPool>,
+    authenticated_user: AuthenticatedUser,
+    change_email_dto: Json<ChangeEmailDto>,
+) -> Result<impl Responder, Error> {
+    let email_status_dto = account_interaction::change_email(
+        database_connection_pool.into_inner(),
+        authenticated_user,
+        change_email_dto.into_inner(),
+    )
+    .await?;
+
+    Ok(Json(email_status_dto))
+}
+
+async fn verify_email(
+    database_connection_pool: Data<PostgresDatabaseConnectionPool>,
+    account_token_dto: Json<AccountTokenDto>,
+) -> Result<impl Responder, Error> {
+    let email_status_dto = account_interaction::verify_email(
+        database_connection_pool.into_inner(),
+        account_token_dto.into_inner(),
+    )
+    .await?;
+
+    Ok(Json(email_status_dto))
+}
+
+async fn request_password_reset(
+    request: HttpRequest,
+    database_connection_pool: Data<PostgresDatabaseConnectionPool>,
+    email_dto: Json<EmailDto>,
+) -> Result<impl Responder, Error> {
+    account_interaction::request_password_reset(
+        request,
+        database_connection_pool.into_inner(),
+        email_dto.into_inner(),
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok())
+}
+
+async fn reset_password(
+    database_connection_pool: Data<PostgresDatabaseConnectionPool>,
+    reset_password_dto: Json<ResetPasswordDto>,
+) -> Result<impl Responder, Error> {
+    account_interaction::reset_password(
+        database_connection_pool.into_inner(),
+        reset_password_dto.into_inner(),
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok())
+}
+
+async fn start_totp_enrollment(
+    database_connection_pool: Data<PostgresDatabaseConnectionPool>,
+    authenticated_user: AuthenticatedUser,
+    password_dto: Json<PasswordDto>,
+) -> Result<impl Responder, Error> {
+    let totp_enrollment_dto = account_interaction::start_totp_enrollment(
+        database_connection_pool.into_inner(),
+        authenticated_user,
+        password_dto.into_inner(),
+    )
+    .await?;
+
+    Ok(Json(totp_enrollment_dto))
+}
+
+async fn confirm_totp_enrollment(
+    database_connection_pool: Data<PostgresDatabaseConnectionPool>,
+    authenticated_user: AuthenticatedUser,
+    confirm_totp_enrollment_dto: Json<ConfirmTotpEnrollmentDto>,
+) -> Result<impl Responder, Error> {
+    let recovery_codes_dto = account_interaction::confirm_totp_enrollment(
+        database_connection_pool.into_inner(),
+        authenticated_user,
+        confirm_totp_enrollment_dto.into_inner(),
+    )
+    .await?;
+
+    Ok(Json(recovery_codes_dto))
+}
+
+async fn disable_totp(
+    database_connection_pool: Data<PostgresDatabaseConnectionPool>,
+    authenticated_user: AuthenticatedUser,
+    disable_totp_dto: Json<DisableTotpDto>,
+) -> Result<impl Responder, Error> {
+    account_interaction::disable_totp(
+        database_connection_pool.into_inner(),
+        authenticated_user,
+        disable_totp_dto.into_inner(),
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok())
+}
+
+async fn get_sessions(
+    database_connection_pool: Data<PostgresDatabaseConnectionPool>,
+    authenticated_user: AuthenticatedUser,
+) -> Result<impl Responder, Error> {
+    let session_dtos = account_interaction::get_sessions(
+        database_connection_pool.into_inner(),
+        authenticated_user,
+    )
+    .await?;
+
+    Ok(Json(session_dtos))
+}
+
+async fn delete_session(
+    database_connection_pool: Data<PostgresDatabaseConnectionPool>,
+    authenticated_user: AuthenticatedUser,
+    session_id: Path<String>,
+) -> Result<impl Responder, Error> {
+    account_interaction::delete_session(
+        database_connection_pool.into_inner(),
+        authenticated_user,
+        session_id.into_inner(),
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok())
+}
+
+// Signs out everywhere except current device
+async fn delete_other_sessions(
+    database_connection_pool: Data<PostgresDatabaseConnectionPool>,
+    authenticated_user: AuthenticatedUser,
+) -> Result<impl Responder, Error> {
+    account_interaction::delete_other_sessions(
+        database_connection_pool.into_inner(),
+        authenticated_user,
+    )
+    .await?;
 
-    Json(auth_token_dto)
+    Ok(HttpResponse::Ok())
 }
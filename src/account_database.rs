@@ -1,93 +1,384 @@
 use super::{
-    account_entity::{InsertableUserAccountEntity, UserAccountEntity},
+    account_entity::{
+        InsertableUserAccountEntity, RecoveryCodeEntity, UserAccountCredentialsEntity,
+        UserAccountEntity,
+    },
+    error_data::Error,
     postgres_database_connection::{establish_database_connection, PostgresDatabaseConnectionPool},
-    schema::user_account,
+    schema::{recovery_code, user_account},
+    security_data::HashData,
+};
+use diesel::{
+    dsl::exists,
+    prelude::*,
+    result::{DatabaseErrorKind, Error as DieselError},
+    sql_types::{Nullable, Text},
 };
-use diesel::prelude::*;
 use std::sync::Arc;
 
+// User names are unique regardless of case
+sql_function!(fn lower(x: Nullable<Text>) -> Nullable<Text>);
+
 pub async fn insert_user_account(
     database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
     insertable_user_account_entity: InsertableUserAccountEntity,
-) -> UserAccountEntity {
-    let database_connection = establish_database_connection(database_connection_pool);
+) -> Result<UserAccountEntity, Error> {
+    let database_connection = establish_database_connection(database_connection_pool)?;
 
-    let user_account_entity = diesel::insert_into(user_account::table)
+    let user_account_entity: UserAccountEntity = diesel::insert_into(user_account::table)
         .values(&insertable_user_account_entity)
         .get_result(&database_connection)
-        .expect("Error inserting user account");
+        .map_err(|error| match error {
+            DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                Error::UserNameTaken
+            }
+            _ => Error::from(error),
+        })?;
 
     println!("Sucessfully inserted {}'s account", user_account_entity);
 
-    user_account_entity
+    Ok(user_account_entity)
+}
+
+pub async fn get_user_account(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    user_id: String,
+) -> Result<UserAccountEntity, Error> {
+    let database_connection = establish_database_connection(database_connection_pool)?;
+
+    let user_account_entity: UserAccountEntity = user_account::table
+        .find(user_id)
+        .first(&database_connection)
+        .map_err(map_not_found_error)?;
+
+    println!("Sucessfully loaded {}'s account", user_account_entity);
+
+    Ok(user_account_entity)
 }
 
 pub async fn get_user_account_by_user_name(
     database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
     user_name: String,
-) -> UserAccountEntity {
-    let database_connection = establish_database_connection(database_connection_pool);
+) -> Result<UserAccountEntity, Error> {
+    let database_connection = establish_database_connection(database_connection_pool)?;
 
-    let user_account_entity = user_account::table
-        .filter(user_account::user_name.eq(Some(user_name)))
+    let user_account_entity: UserAccountEntity = user_account::table
+        .filter(lower(user_account::user_name).eq(lower(Some(user_name))))
         .first(&database_connection)
-        .expect("Error loading user account with user name");
+        .map_err(map_not_found_error)?;
 
     println!("Sucessfully loaded {}'s account", user_account_entity);
 
-    user_account_entity
+    Ok(user_account_entity)
 }
 
-pub async fn get_user_account_by_access_token(
+// Only guest's account can be upgraded, so user_id and all user's data are kept
+pub async fn upgrade_guest_account(
     database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
-    access_token: String,
-) -> UserAccountEntity {
-    let database_connection = establish_database_connection(database_connection_pool);
+    user_id: String,
+    user_account_credentials_entity: UserAccountCredentialsEntity,
+) -> Result<UserAccountEntity, Error> {
+    let database_connection = establish_database_connection(database_connection_pool)?;
 
-    let user_account_entity = user_account::table
-        .filter(user_account::access_token.eq(access_token))
+    let source = user_account::table
+        .find(user_id)
+        .filter(user_account::user_name.is_null());
+    let user_account_entity: UserAccountEntity = diesel::update(source)
+        .set(&user_account_credentials_entity)
+        .get_result(&database_connection)
+        .map_err(|error| match error {
+            DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                Error::UserNameTaken
+            }
+            DieselError::NotFound => Error::AccountAlreadyRegistered,
+            _ => Error::from(error),
+        })?;
+
+    println!(
+        "Sucessfully upgraded guest to {}'s account",
+        user_account_entity
+    );
+
+    Ok(user_account_entity)
+}
+
+// Only verified email addresses are used, so that nobody can reset password with somebody else's one
+pub async fn get_user_account_by_verified_email(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    email: String,
+) -> Result<UserAccountEntity, Error> {
+    let database_connection = establish_database_connection(database_connection_pool)?;
+
+    let user_account_entity: UserAccountEntity = user_account::table
+        .filter(lower(user_account::email).eq(lower(Some(email))))
+        .filter(user_account::is_email_verified.eq(true))
         .first(&database_connection)
-        .expect("Error loading user account with access token");
+        .map_err(map_not_found_error)?;
 
     println!("Sucessfully loaded {}'s account", user_account_entity);
 
-    user_account_entity
+    Ok(user_account_entity)
 }
 
-pub async fn update_auth_token(
+pub async fn is_user_name_taken(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    user_name: String,
+) -> Result<bool, Error> {
+    let database_connection = establish_database_connection(database_connection_pool)?;
+
+    let is_user_name_taken = diesel::select(exists(
+        user_account::table.filter(lower(user_account::user_name).eq(lower(Some(user_name)))),
+    ))
+    .get_result(&database_connection)?;
+
+    Ok(is_user_name_taken)
+}
+
+// Guest has no user name to change, it has to be upgraded instead
+pub async fn update_user_name(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    user_id: String,
+    user_name: String,
+) -> Result<UserAccountEntity, Error> {
+    let database_connection = establish_database_connection(database_connection_pool)?;
+
+    let source = user_account::table
+        .find(user_id)
+        .filter(user_account::user_name.is_not_null());
+    let user_account_entity: UserAccountEntity = diesel::update(source)
+        .set(user_account::user_name.eq(Some(user_name)))
+        .get_result(&database_connection)
+        .map_err(|error| match error {
+            DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                Error::UserNameTaken
+            }
+            DieselError::NotFound => Error::AccountNotRegistered,
+            _ => Error::from(error),
+        })?;
+
+    println!("Sucessfully renamed account to {}", user_account_entity);
+
+    Ok(user_account_entity)
+}
+
+// Verified email address replaces current one, it's taken, if another account has already verified it
+pub async fn verify_email(
     database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
     user_id: String,
-    access_token: String,
-    refresh_token: String,
-) -> UserAccountEntity {
-    let database_connection = establish_database_connection(database_connection_pool);
+    email: String,
+) -> Result<UserAccountEntity, Error> {
+    let database_connection = establish_database_connection(database_connection_pool)?;
 
-    let source = user_account::table.find(user_id);
-    let user_account_entity = diesel::update(source)
+    let source = user_account::table
+        .find(user_id)
+        .filter(user_account::user_name.is_not_null());
+    let user_account_entity: UserAccountEntity = diesel::update(source)
         .set((
-            (user_account::access_token.eq(access_token)),
-            (user_account::refresh_token.eq(refresh_token)),
+            (user_account::email.eq(Some(email))),
+            (user_account::is_email_verified.eq(true)),
         ))
         .get_result(&database_connection)
-        .expect("Error loading user account with access token");
+        .map_err(|error| match error {
+            DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => Error::EmailTaken,
+            DieselError::NotFound => Error::InvalidAccountToken,
+            _ => Error::from(error),
+        })?;
+
+    println!("Sucessfully verified {}'s email", user_account_entity);
+
+    Ok(user_account_entity)
+}
+
+pub async fn update_password(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    user_id: String,
+    password_hash_data: HashData,
+) -> Result<UserAccountEntity, Error> {
+    let database_connection = establish_database_connection(database_connection_pool)?;
+
+    let user_account_entity: UserAccountEntity = diesel::update(user_account::table.find(user_id))
+        .set((
+            (user_account::password_hash.eq(Some(password_hash_data.hash))),
+            (user_account::password_hash_salt.eq(Some(password_hash_data.salt))),
+            (user_account::password_hash_algorithm.eq(Some(password_hash_data.algorithm))),
+        ))
+        .get_result(&database_connection)
+        .map_err(map_not_found_error)?;
+
+    println!("Sucessfully updated {}'s password", user_account_entity);
+
+    Ok(user_account_entity)
+}
+
+// Secret isn't required on sign in, until enrollment is confirmed
+pub async fn start_totp_enrollment(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    user_id: String,
+    totp_secret: String,
+) -> Result<UserAccountEntity, Error> {
+    let database_connection = establish_database_connection(database_connection_pool)?;
+
+    let source = user_account::table
+        .find(user_id)
+        .filter(user_account::is_totp_enabled.eq(false));
+    let user_account_entity: UserAccountEntity = diesel::update(source)
+        .set(user_account::totp_secret.eq(Some(totp_secret)))
+        .get_result(&database_connection)
+        .map_err(|error| match error {
+            DieselError::NotFound => Error::TotpAlreadyEnabled,
+            _ => Error::from(error),
+        })?;
 
-    println!("Sucessfully updated {}'s account", user_account_entity);
+    println!(
+        "Sucessfully started {}'s two-factor authentication enrollment",
+        user_account_entity
+    );
 
-    user_account_entity
+    Ok(user_account_entity)
 }
 
+// Previous recovery codes are replaced with new ones
+pub async fn enable_totp(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    user_id: String,
+    totp_step: i64,
+    recovery_code_entities: Vec<RecoveryCodeEntity>,
+) -> Result<UserAccountEntity, Error> {
+    let database_connection = establish_database_connection(database_connection_pool)?;
+
+    let user_account_entity: UserAccountEntity = database_connection
+        .transaction::<_, DieselError, _>(|| {
+            let source = user_account::table
+                .find(&user_id)
+                .filter(user_account::is_totp_enabled.eq(false))
+                .filter(user_account::totp_secret.is_not_null());
+            let user_account_entity = diesel::update(source)
+                .set((
+                    (user_account::is_totp_enabled.eq(true)),
+                    (user_account::totp_last_used_step.eq(Some(totp_step))),
+                ))
+                .get_result(&database_connection)?;
+
+            diesel::delete(recovery_code::table.filter(recovery_code::user_id.eq(&user_id)))
+                .execute(&database_connection)?;
+            diesel::insert_into(recovery_code::table)
+                .values(&recovery_code_entities)
+                .execute(&database_connection)?;
+
+            Ok(user_account_entity)
+        })
+        .map_err(|error| match error {
+            DieselError::NotFound => Error::TotpAlreadyEnabled,
+            _ => Error::from(error),
+        })?;
+
+    println!(
+        "Sucessfully enabled {}'s two-factor authentication",
+        user_account_entity
+    );
+
+    Ok(user_account_entity)
+}
+
+// Time step is saved only if it's later than the last used one, so that code can't be replayed
+pub async fn use_totp_step(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    user_id: String,
+    totp_step: i64,
+) -> Result<(), Error> {
+    let database_connection = establish_database_connection(database_connection_pool)?;
+
+    let source = user_account::table.find(user_id).filter(
+        user_account::totp_last_used_step
+            .is_null()
+            .or(user_account::totp_last_used_step.lt(totp_step)),
+    );
+    let num_updated = diesel::update(source)
+        .set(user_account::totp_last_used_step.eq(Some(totp_step)))
+        .execute(&database_connection)?;
+
+    if num_updated == 0 {
+        Err(Error::WrongTotpCode)
+    } else {
+        Ok(())
+    }
+}
+
+pub async fn disable_totp(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    user_id: String,
+) -> Result<UserAccountEntity, Error> {
+    let database_connection = establish_database_connection(database_connection_pool)?;
+
+    let user_account_entity: UserAccountEntity = database_connection
+        .transaction::<_, DieselError, _>(|| {
+            diesel::delete(recovery_code::table.filter(recovery_code::user_id.eq(&user_id)))
+                .execute(&database_connection)?;
+
+            diesel::update(user_account::table.find(&user_id))
+                .set((
+                    (user_account::totp_secret.eq(None::<String>)),
+                    (user_account::is_totp_enabled.eq(false)),
+                    (user_account::totp_last_used_step.eq(None::<i64>)),
+                ))
+                .get_result(&database_connection)
+        })
+        .map_err(map_not_found_error)?;
+
+    println!(
+        "Sucessfully disabled {}'s two-factor authentication",
+        user_account_entity
+    );
+
+    Ok(user_account_entity)
+}
+
+pub async fn get_recovery_codes(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    user_id: String,
+) -> Result<Vec<RecoveryCodeEntity>, Error> {
+    let database_connection = establish_database_connection(database_connection_pool)?;
+
+    let recovery_code_entities = recovery_code::table
+        .filter(recovery_code::user_id.eq(user_id))
+        .load(&database_connection)?;
+
+    Ok(recovery_code_entities)
+}
+
+// Returns number of deleted codes, which is 0, if code was already used by concurrent request
+pub async fn delete_recovery_code(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    recovery_code_id: String,
+) -> Result<usize, Error> {
+    let database_connection = establish_database_connection(database_connection_pool)?;
+
+    let num_deleted = diesel::delete(recovery_code::table.find(recovery_code_id))
+        .execute(&database_connection)?;
+
+    println!("Deleted {} recovery codes", num_deleted);
+
+    Ok(num_deleted)
+}
+
+// User account's sessions and notes are deleted along with it
 pub async fn delete_user_account(
     database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
-    access_token: String,
-) -> usize {
-    let database_connection = establish_database_connection(database_connection_pool.clone());
+    user_id: String,
+) -> Result<usize, Error> {
+    let database_connection = establish_database_connection(database_connection_pool)?;
 
-    let delete_source = user_account::table.filter(user_account::access_token.eq(access_token));
-    let num_deleted = diesel::delete(delete_source)
-        .execute(&database_connection)
-        .expect("Error deleting user account");
+    let num_deleted =
+        diesel::delete(user_account::table.find(user_id)).execute(&database_connection)?;
 
     println!("Deleted {} users", num_deleted);
 
-    num_deleted
+    Ok(num_deleted)
+}
+
+fn map_not_found_error(error: DieselError) -> Error {
+    match error {
+        DieselError::NotFound => Error::UserAccountNotFound,
+        _ => Error::from(error),
+    }
 }
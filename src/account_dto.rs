@@ -1,3 +1,4 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
 #[derive(Deserialize)]
@@ -8,20 +9,185 @@ pub struct CredentialsDto {
     pub password: String,
 }
 
+#[derive(Deserialize, Serialize)]
+pub struct UserNameDto {
+    #[serde(rename = "userName")]
+    pub user_name: String,
+}
+
+#[derive(Deserialize)]
+pub struct UserNameQueryDto {
+    #[serde(rename(deserialize = "userName"))]
+    pub user_name: String,
+}
+
+#[derive(Serialize)]
+pub struct UserNameAvailabilityDto {
+    #[serde(rename(serialize = "userName"))]
+    pub user_name: String,
+    #[serde(rename(serialize = "isAvailable"))]
+    pub is_available: bool,
+}
+
+#[derive(Deserialize)]
+pub struct EmailDto {
+    #[serde(rename(deserialize = "email"))]
+    pub email: String,
+}
+
+#[derive(Deserialize)]
+pub struct ChangeEmailDto {
+    #[serde(rename(deserialize = "password"))]
+    pub password: String,
+    #[serde(rename(deserialize = "email"))]
+    pub email: String,
+}
+
+#[derive(Serialize)]
+pub struct EmailStatusDto {
+    #[serde(rename(serialize = "email"))]
+    pub email: String,
+    #[serde(rename(serialize = "isVerified"))]
+    pub is_verified: bool,
+}
+
+// Token, that was sent by mail
+#[derive(Deserialize)]
+pub struct AccountTokenDto {
+    #[serde(rename(deserialize = "token"))]
+    pub token: String,
+}
+
+#[derive(Deserialize)]
+pub struct ResetPasswordDto {
+    #[serde(rename(deserialize = "token"))]
+    pub token: String,
+    #[serde(rename(deserialize = "newPassword"))]
+    pub new_password: String,
+}
+
+#[derive(Deserialize)]
+pub struct ChangePasswordDto {
+    #[serde(rename(deserialize = "currentPassword"))]
+    pub current_password: String,
+    #[serde(rename(deserialize = "newPassword"))]
+    pub new_password: String,
+}
+
 #[derive(Deserialize)]
 pub struct SignUpDataDto {
     #[serde(rename(deserialize = "credentials"))]
     pub credentials_dto: Option<CredentialsDto>,
 }
 
+// Guest signing in gets guest's notes merged into account, unless guest's notes are discarded
+#[derive(Deserialize)]
+pub struct SignInDataDto {
+    #[serde(flatten)]
+    pub credentials_dto: CredentialsDto,
+    #[serde(rename(deserialize = "discardGuestNotes"), default)]
+    pub discard_guest_notes: bool,
+}
+
+// Second step of sign in, when two-factor authentication is enabled
+#[derive(Deserialize)]
+pub struct SecondFactorDto {
+    #[serde(rename(deserialize = "mfaChallengeToken"))]
+    pub mfa_challenge_token: String,
+    // Either TOTP code or recovery code
+    #[serde(rename(deserialize = "code"))]
+    pub code: String,
+    #[serde(rename(deserialize = "discardGuestNotes"), default)]
+    pub discard_guest_notes: bool,
+}
+
+// Sign in either is complete or needs second factor
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum SignInResultDto {
+    Profile(ProfileDto),
+    MfaChallenge(MfaChallengeDto),
+}
+
+#[derive(Serialize)]
+pub struct MfaChallengeDto {
+    #[serde(rename(serialize = "mfaChallengeToken"))]
+    pub mfa_challenge_token: String,
+}
+
+#[derive(Serialize)]
+pub struct TotpEnrollmentDto {
+    #[serde(rename(serialize = "secret"))]
+    pub secret: String,
+    #[serde(rename(serialize = "uri"))]
+    pub uri: String,
+}
+
+#[derive(Deserialize)]
+pub struct PasswordDto {
+    #[serde(rename(deserialize = "password"))]
+    pub password: String,
+}
+
+#[derive(Deserialize)]
+pub struct ConfirmTotpEnrollmentDto {
+    #[serde(rename(deserialize = "password"))]
+    pub password: String,
+    #[serde(rename(deserialize = "code"))]
+    pub code: String,
+}
+
+#[derive(Serialize)]
+pub struct RecoveryCodesDto {
+    #[serde(rename(serialize = "recoveryCodes"))]
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct DisableTotpDto {
+    #[serde(rename(deserialize = "password"))]
+    pub password: String,
+    // Either TOTP code or recovery code
+    #[serde(rename(deserialize = "code"))]
+    pub code: String,
+}
+
 #[derive(Serialize)]
 pub struct ProfileDto {
     #[serde(rename(serialize = "userId"))]
     pub user_id: String,
     #[serde(rename(serialize = "userName"))]
     pub user_name: Option<String>,
+    #[serde(rename(serialize = "email"))]
+    pub email: Option<String>,
+    #[serde(rename(serialize = "isEmailVerified"))]
+    pub is_email_verified: bool,
     #[serde(rename(serialize = "authToken"))]
     pub auth_token_dto: AuthTokenDto,
+    #[serde(
+        rename(serialize = "guestNotesMergeReport"),
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub guest_notes_merge_report_dto: Option<GuestNotesMergeReportDto>,
+}
+
+#[derive(Serialize)]
+pub struct GuestNotesMergeReportDto {
+    #[serde(rename(serialize = "mergedNotes"))]
+    pub merged_notes: usize,
+    #[serde(rename(serialize = "rekeyedNotes"))]
+    pub rekeyed_note_dtos: Vec<RekeyedNoteDto>,
+    #[serde(rename(serialize = "discardedNotes"))]
+    pub discarded_notes: usize,
+}
+
+// Guest's note, that got new id, because account already had note with the same id
+#[derive(Serialize)]
+pub struct RekeyedNoteDto {
+    #[serde(rename(serialize = "oldNoteId"))]
+    pub old_note_id: String,
+    #[serde(rename(serialize = "newNoteId"))]
+    pub new_note_id: String,
 }
 
 #[derive(Serialize)]
@@ -31,3 +197,20 @@ pub struct AuthTokenDto {
     #[serde(rename(serialize = "refreshToken"))]
     pub refresh_token: String,
 }
+
+#[derive(Serialize)]
+pub struct SessionDto {
+    #[serde(rename(serialize = "sessionId"))]
+    pub session_id: String,
+    #[serde(rename(serialize = "deviceName"))]
+    pub device_name: Option<String>,
+    #[serde(rename(serialize = "userAgent"))]
+    pub user_agent: Option<String>,
+    #[serde(rename(serialize = "createdAt"))]
+    pub created_at: DateTime<Utc>,
+    // Time of last refresh, access tokens are used without updating it
+    #[serde(rename(serialize = "lastUsedAt"))]
+    pub last_used_at: DateTime<Utc>,
+    #[serde(rename(serialize = "isCurrent"))]
+    pub is_current: bool,
+}
@@ -1,9 +1,10 @@
 use super::{
-    schema::user_account,
+    error_data::Error,
+    schema::{recovery_code, user_account},
     security,
-    security_data::{AuthToken, HashAlgorithm},
+    security_data::HashAlgorithm,
 };
-use diesel::{Insertable, Queryable};
+use diesel::{AsChangeset, Insertable, Queryable};
 use std::{
     fmt::{Display, Formatter},
     time::SystemTime,
@@ -18,47 +19,52 @@ pub struct InsertableUserAccountEntity {
     pub password_hash: Option<String>,
     pub password_hash_salt: Option<String>,
     pub password_hash_algorithm: Option<HashAlgorithm>,
-    pub access_token: String,
-    pub refresh_token: String,
 }
 
 impl InsertableUserAccountEntity {
     pub fn guest() -> Self {
-        let (uuid, auth_token) = Self::get_uuid_and_auth_token();
-
         InsertableUserAccountEntity {
-            user_id: uuid.to_string(),
+            user_id: Uuid::new_v4().to_string(),
             user_name: None,
             password_hash: None,
             password_hash_salt: None,
             password_hash_algorithm: None,
-            access_token: auth_token.access_token,
-            refresh_token: auth_token.refresh_token,
         }
     }
 
-    pub fn registered_user(user_name: String, password: String) -> Self {
-        let (uuid, auth_token) = Self::get_uuid_and_auth_token();
-
-        let password_hash_data = security::generate_password_hash(password.as_bytes());
+    pub fn registered_user(user_name: String, password: String) -> Result<Self, Error> {
+        let password_hash_data = security::generate_password_hash(password.as_bytes())?;
 
-        InsertableUserAccountEntity {
-            user_id: uuid.to_string(),
+        Ok(InsertableUserAccountEntity {
+            user_id: Uuid::new_v4().to_string(),
             user_name: Some(user_name),
             password_hash: Some(password_hash_data.hash),
             password_hash_salt: Some(password_hash_data.salt),
             password_hash_algorithm: Some(password_hash_data.algorithm),
-            access_token: auth_token.access_token,
-            refresh_token: auth_token.refresh_token,
-        }
+        })
     }
+}
 
-    fn get_uuid_and_auth_token() -> (Uuid, AuthToken) {
-        let uuid = Uuid::new_v4();
+// Credentials, that guest's account is upgraded with
+#[derive(AsChangeset)]
+#[table_name = "user_account"]
+pub struct UserAccountCredentialsEntity {
+    pub user_name: String,
+    pub password_hash: String,
+    pub password_hash_salt: String,
+    pub password_hash_algorithm: HashAlgorithm,
+}
 
-        let auth_token = security::generate_auth_token(uuid.to_string());
+impl UserAccountCredentialsEntity {
+    pub fn new(user_name: String, password: String) -> Result<Self, Error> {
+        let password_hash_data = security::generate_password_hash(password.as_bytes())?;
 
-        (uuid, auth_token)
+        Ok(UserAccountCredentialsEntity {
+            user_name,
+            password_hash: password_hash_data.hash,
+            password_hash_salt: password_hash_data.salt,
+            password_hash_algorithm: password_hash_data.algorithm,
+        })
     }
 }
 
@@ -70,8 +76,11 @@ pub struct UserAccountEntity {
     pub password_hash: Option<String>,
     pub password_hash_salt: Option<String>,
     pub password_hash_algorithm: Option<HashAlgorithm>,
-    pub access_token: String,
-    pub refresh_token: String,
+    pub email: Option<String>,
+    pub is_email_verified: bool,
+    pub totp_secret: Option<String>,
+    pub is_totp_enabled: bool,
+    pub totp_last_used_step: Option<i64>,
 }
 
 impl Display for UserAccountEntity {
@@ -83,3 +92,29 @@ impl Display for UserAccountEntity {
         }
     }
 }
+
+#[derive(Insertable, Queryable)]
+#[table_name = "recovery_code"]
+pub struct RecoveryCodeEntity {
+    pub recovery_code_id: String,
+    pub user_id: String,
+    pub code_hash: String,
+}
+
+impl RecoveryCodeEntity {
+    // Only hash of recovery code is stored, so raw recovery code is returned along with entity
+    pub fn new(user_id: String) -> Result<(Self, String), Error> {
+        let recovery_code = security::generate_recovery_code();
+        let recovery_code_hash_data = security::generate_password_hash(
+            security::normalize_recovery_code(&recovery_code).as_bytes(),
+        )?;
+
+        let recovery_code_entity = RecoveryCodeEntity {
+            recovery_code_id: Uuid::new_v4().to_string(),
+            user_id,
+            code_hash: recovery_code_hash_data.hash,
+        };
+
+        Ok((recovery_code_entity, recovery_code))
+    }
+}
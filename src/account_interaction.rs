@@ -1,167 +1,1687 @@
 use super::{
     account_database,
-    account_dto::{AuthTokenDto, CredentialsDto, ProfileDto, SignUpDataDto},
-    account_entity::{InsertableUserAccountEntity, UserAccountEntity},
+    account_dto::{
+        AccountTokenDto, AuthTokenDto, ChangeEmailDto, ChangePasswordDto, ConfirmTotpEnrollmentDto,
+        CredentialsDto, DisableTotpDto, EmailDto, EmailStatusDto, GuestNotesMergeReportDto,
+        MfaChallengeDto, PasswordDto, ProfileDto, RecoveryCodesDto, RekeyedNoteDto,
+        ResetPasswordDto, SecondFactorDto, SessionDto, SignInDataDto, SignInResultDto,
+        SignUpDataDto, TotpEnrollmentDto, UserNameAvailabilityDto, UserNameDto,
+    },
+    account_entity::{
+        InsertableUserAccountEntity, RecoveryCodeEntity, UserAccountCredentialsEntity,
+        UserAccountEntity,
+    },
+    account_token_database,
+    account_token_entity::AccountTokenEntity,
+    error_data::Error,
+    mailer::{self, Mail},
+    notes_database,
     postgres_database_connection::PostgresDatabaseConnectionPool,
+    rate_limiter::{
+        CURRENT_PASSWORD_RATE_LIMITER, EMAIL_CHANGE_RATE_LIMITER, PASSWORD_RESET_RATE_LIMITER,
+        SECOND_FACTOR_RATE_LIMITER, USER_NAME_AVAILABILITY_RATE_LIMITER,
+    },
     security,
-    security_data::PasswordStrengthIssue,
+    security_data::{AccountTokenPurpose, AuthToken, AuthenticatedUser, TokenType},
+    session_cache, session_database,
+    session_entity::{ConsumedRefreshTokenEntity, InsertableSessionEntity, SessionEntity},
 };
-use actix_web::HttpRequest;
-use std::sync::Arc;
+use actix_web::{http::header::USER_AGENT, HttpRequest};
+use chrono::Utc;
+use std::{sync::Arc, time::SystemTime};
 
+const DEVICE_NAME_HEADER_KEY: &str = "Device-Name";
+const RECOVERY_CODES_COUNT: usize = 10;
+
+// Authenticated user is present, if guest signs up or in
 pub async fn sign_up(
     request: HttpRequest,
     database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    authenticated_user: Option<AuthenticatedUser>,
     sign_up_data_dto: SignUpDataDto,
-) -> ProfileDto {
+) -> Result<ProfileDto, Error> {
     match sign_up_data_dto.credentials_dto {
         Some(credentials_dto) => {
-            sign_up_as_user(request, database_connection_pool, credentials_dto).await
+            sign_up_as_user(
+                request,
+                database_connection_pool,
+                authenticated_user,
+                credentials_dto,
+            )
+            .await
         }
-        None => sign_up_as_guest(database_connection_pool).await,
+        None => sign_up_as_guest(request, database_connection_pool).await,
     }
 }
 
 pub async fn sign_in(
     request: HttpRequest,
     database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
-    credentials_dto: CredentialsDto,
-) -> ProfileDto {
+    authenticated_user: Option<AuthenticatedUser>,
+    sign_in_data_dto: SignInDataDto,
+) -> Result<SignInResultDto, Error> {
+    let credentials_dto = sign_in_data_dto.credentials_dto;
     println!("Received {}", &credentials_dto.user_name);
 
     let user_account_entity = account_database::get_user_account_by_user_name(
         database_connection_pool.clone(),
-        credentials_dto.user_name,
+        security::normalize_user_name(&credentials_dto.user_name),
+    )
+    .await
+    .map_err(|error| match error {
+        Error::UserAccountNotFound => Error::WrongCredentials,
+        _ => error,
+    })?;
+
+    let password_hash = user_account_entity
+        .password_hash
+        .as_ref()
+        .ok_or(Error::WrongCredentials)?;
+
+    if !security::verify_password(credentials_dto.password.as_bytes(), password_hash)? {
+        return Err(Error::WrongCredentials);
+    }
+
+    // Session is started only after second factor is checked
+    if user_account_entity.is_totp_enabled {
+        return Ok(SignInResultDto::MfaChallenge(MfaChallengeDto {
+            mfa_challenge_token: security::generate_mfa_challenge_token(
+                user_account_entity.user_id,
+            )?,
+        }));
+    }
+
+    user_sucessfully_authorized(
+        request,
+        database_connection_pool,
+        authenticated_user,
+        user_account_entity,
+        sign_in_data_dto.discard_guest_notes,
+    )
+    .await
+    .map(SignInResultDto::Profile)
+}
+
+// Challenge token proves, that password was already checked
+pub async fn sign_in_with_second_factor(
+    request: HttpRequest,
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    authenticated_user: Option<AuthenticatedUser>,
+    second_factor_dto: SecondFactorDto,
+) -> Result<ProfileDto, Error> {
+    let mfa_challenge_token_claims = security::decode_jwt(
+        &second_factor_dto.mfa_challenge_token,
+        TokenType::MfaChallenge,
+    )
+    .map_err(|_| Error::WrongCredentials)?;
+
+    let user_account_entity = account_database::get_user_account(
+        database_connection_pool.clone(),
+        mfa_challenge_token_claims.sub,
+    )
+    .await?;
+
+    verify_second_factor(
+        database_connection_pool.clone(),
+        &user_account_entity,
+        &second_factor_dto.code,
     )
-    .await;
+    .await?;
 
-    let password_hash = user_account_entity.password_hash.as_ref().unwrap().as_str();
+    user_sucessfully_authorized(
+        request,
+        database_connection_pool,
+        authenticated_user,
+        user_account_entity,
+        second_factor_dto.discard_guest_notes,
+    )
+    .await
+}
 
-    if security::verify_password(credentials_dto.password.as_bytes(), password_hash) {
-        user_sucessfully_authorized(request, database_connection_pool, user_account_entity).await
+// Guest gets credentials with all its notes being kept, guest's session is replaced
+// with registered user's one, because tokens of guests might have other lifetimes
+pub async fn upgrade_guest(
+    request: HttpRequest,
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    authenticated_user: AuthenticatedUser,
+    credentials_dto: CredentialsDto,
+) -> Result<ProfileDto, Error> {
+    println!("Received {}", &credentials_dto.user_name);
+
+    let user_name = security::validate_user_name(&credentials_dto.user_name)?;
+
+    let password_strength_issues =
+        security::check_password_strength(&credentials_dto.password, &security::PASSWORD_POLICY);
+    if !password_strength_issues.is_empty() {
+        return Err(Error::WeakPassword(password_strength_issues));
+    }
+
+    let user_account_credentials_entity =
+        UserAccountCredentialsEntity::new(user_name, credentials_dto.password)?;
+
+    let user_account_entity = account_database::upgrade_guest_account(
+        database_connection_pool.clone(),
+        authenticated_user.user_id,
+        user_account_credentials_entity,
+    )
+    .await?;
+
+    session_database::delete_session(
+        database_connection_pool.clone(),
+        authenticated_user.session_id.clone(),
+    )
+    .await?;
+    session_cache::revoke_sessions(vec![authenticated_user.session_id]);
+
+    start_session(&request, database_connection_pool, user_account_entity).await
+}
+
+// Guest can't sign in back, so guest's account is deleted on sign out
+pub async fn sign_out(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    authenticated_user: AuthenticatedUser,
+) -> Result<(), Error> {
+    let user_account_entity = account_database::get_user_account(
+        database_connection_pool.clone(),
+        authenticated_user.user_id.clone(),
+    )
+    .await?;
+
+    if user_account_entity.user_name.is_none() {
+        delete_user_account(database_connection_pool, authenticated_user.user_id).await
     } else {
-        panic!("Password was not verified")
+        session_database::delete_session(
+            database_connection_pool,
+            authenticated_user.session_id.clone(),
+        )
+        .await?;
+        session_cache::revoke_sessions(vec![authenticated_user.session_id]);
+        Ok(())
     }
 }
 
 pub async fn delete_account(
     request: HttpRequest,
     database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
-) -> ProfileDto {
-    let access_token = security::get_access_token_from_request_headers(request.headers());
-    account_database::delete_user_account(database_connection_pool.clone(), access_token).await;
+    authenticated_user: AuthenticatedUser,
+) -> Result<ProfileDto, Error> {
+    delete_user_account(database_connection_pool.clone(), authenticated_user.user_id).await?;
 
-    sign_up_as_guest(database_connection_pool).await
+    sign_up_as_guest(request, database_connection_pool).await
 }
 
+// Refresh token authenticates by itself, so tokens can be refreshed after access token expired.
+// Once refresh token expires, only signing in again starts a new session
 pub async fn refresh_token(
-    request: HttpRequest,
     database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
     refresh_token: String,
-) -> AuthTokenDto {
+) -> Result<AuthTokenDto, Error> {
     println!("Refresh token");
 
-    let access_token = security::get_access_token_from_request_headers(request.headers());
-
-    println!("{}", &access_token);
+    let refresh_token_claims =
+        security::decode_jwt(&refresh_token, TokenType::Refresh).map_err(|error| match error {
+            Error::JWTTokenExpired => Error::RefreshTokenExpired,
+            _ => Error::WrongRefreshToken,
+        })?;
 
-    let user_account_entity = account_database::get_user_account_by_access_token(
+    // Refresh token, that was already used, might have been stolen,
+    // so we can't trust any token of its session anymore
+    if let Some(consumed_refresh_token_entity) = session_database::get_consumed_refresh_token(
         database_connection_pool.clone(),
-        access_token,
+        refresh_token_claims.jti.clone(),
     )
-    .await;
+    .await?
+    {
+        session_database::delete_session(
+            database_connection_pool,
+            consumed_refresh_token_entity.session_id.clone(),
+        )
+        .await?;
+        session_cache::revoke_sessions(vec![consumed_refresh_token_entity.session_id]);
 
-    if refresh_token == user_account_entity.refresh_token {
-        // tbd if refresh token was expired, then create completely new AuthToken
-        let new_access_token = security::refresh_access_token(
-            refresh_token.clone(),
-            user_account_entity.user_id.clone(),
-        );
+        return Err(Error::RefreshTokenReused);
+    }
 
-        let updated_user_account_entity = account_database::update_auth_token(
-            database_connection_pool,
-            user_account_entity.user_id,
-            new_access_token,
-            user_account_entity.refresh_token,
+    let session_entity =
+        session_database::get_session(database_connection_pool.clone(), refresh_token_claims.sid)
+            .await?;
+
+    if refresh_token_claims.jti == session_entity.refresh_token_id
+        && refresh_token_claims.sub == session_entity.user_id
+        && security::verify_refresh_token_hash(&refresh_token, &session_entity.refresh_token_hash)
+    {
+        let user_account_entity = account_database::get_user_account(
+            database_connection_pool.clone(),
+            session_entity.user_id.clone(),
+        )
+        .await?;
+
+        let new_auth_token = security::generate_auth_token(
+            session_entity.user_id.clone(),
+            session_entity.session_id.clone(),
+            user_account_entity.user_name.is_none(),
+            session_entity.created_at.into(),
+        )?;
+
+        let consumed_refresh_token_entity = ConsumedRefreshTokenEntity {
+            refresh_token_id: refresh_token_claims.jti,
+            session_id: session_entity.session_id,
+            consumed_at: SystemTime::now(),
+        };
+
+        let session_id = consumed_refresh_token_entity.session_id.clone();
+        let rotation_result = session_database::rotate_refresh_token(
+            database_connection_pool.clone(),
+            consumed_refresh_token_entity,
+            security::hash_refresh_token(&new_auth_token.refresh_token),
+            new_auth_token.refresh_token_id.clone(),
         )
         .await;
 
-        AuthTokenDto {
-            access_token: updated_user_account_entity.access_token,
-            refresh_token: updated_user_account_entity.refresh_token,
+        // Concurrent request has used the same refresh token, so session is revoked
+        // just like when reused token is found above
+        if let Err(Error::RefreshTokenReused) = rotation_result {
+            session_database::delete_session(database_connection_pool, session_id.clone()).await?;
+            session_cache::revoke_sessions(vec![session_id]);
         }
+        rotation_result?;
+
+        Ok(map_auth_token_to_auth_token_dto(new_auth_token))
     } else {
-        panic!("Wrong refresh token")
+        Err(Error::WrongRefreshToken)
     }
 }
 
+// Name is validated just like on sign up. Requests are limited per client's address,
+// so that route can't be used to quickly enumerate registered users
+pub async fn check_user_name_availability(
+    request: HttpRequest,
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    user_name: String,
+) -> Result<UserNameAvailabilityDto, Error> {
+    if !USER_NAME_AVAILABILITY_RATE_LIMITER.try_acquire(&get_client_address(&request)) {
+        return Err(Error::TooManyRequests);
+    }
+
+    let user_name = security::validate_user_name(&user_name)?;
+
+    let is_user_name_taken =
+        account_database::is_user_name_taken(database_connection_pool, user_name.clone()).await?;
+
+    Ok(UserNameAvailabilityDto {
+        user_name,
+        is_available: !is_user_name_taken,
+    })
+}
+
+// Returns user name in normalized form, that it's stored in
+pub async fn change_user_name(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    authenticated_user: AuthenticatedUser,
+    user_name_dto: UserNameDto,
+) -> Result<UserNameDto, Error> {
+    println!("Received {}", &user_name_dto.user_name);
+
+    let user_name = security::validate_user_name(&user_name_dto.user_name)?;
+
+    let user_account_entity = account_database::update_user_name(
+        database_connection_pool,
+        authenticated_user.user_id,
+        user_name,
+    )
+    .await?;
+
+    Ok(UserNameDto {
+        user_name: user_account_entity
+            .user_name
+            .expect("Registered user has user name"),
+    })
+}
+
+// Current password is required again, so that stolen access token can't be used to take account over.
+// All other sessions are revoked, because they might belong to whoever knew old password
+pub async fn change_password(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    authenticated_user: AuthenticatedUser,
+    change_password_dto: ChangePasswordDto,
+) -> Result<(), Error> {
+    if !CURRENT_PASSWORD_RATE_LIMITER.try_acquire(&authenticated_user.user_id) {
+        return Err(Error::TooManyRequests);
+    }
+
+    let user_account_entity = account_database::get_user_account(
+        database_connection_pool.clone(),
+        authenticated_user.user_id.clone(),
+    )
+    .await?;
+
+    verify_current_password(&user_account_entity, &change_password_dto.current_password)?;
+
+    let password_strength_issues = security::check_password_strength(
+        &change_password_dto.new_password,
+        &security::PASSWORD_POLICY,
+    );
+    if !password_strength_issues.is_empty() {
+        return Err(Error::WeakPassword(password_strength_issues));
+    }
+
+    let password_hash_data =
+        security::generate_password_hash(change_password_dto.new_password.as_bytes())?;
+
+    account_database::update_password(
+        database_connection_pool.clone(),
+        authenticated_user.user_id.clone(),
+        password_hash_data,
+    )
+    .await?;
+
+    let deleted_session_ids = session_database::delete_other_sessions(
+        database_connection_pool,
+        authenticated_user.user_id,
+        authenticated_user.session_id,
+    )
+    .await?;
+    session_cache::revoke_sessions(deleted_session_ids);
+
+    Ok(())
+}
+
+// Two-factor authentication is enabled only after first code from authenticator app is confirmed
+// Password is required for enrollment, so that stolen session can't bind attacker's authenticator
+pub async fn start_totp_enrollment(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    authenticated_user: AuthenticatedUser,
+    password_dto: PasswordDto,
+) -> Result<TotpEnrollmentDto, Error> {
+    let user_account_entity = account_database::get_user_account(
+        database_connection_pool.clone(),
+        authenticated_user.user_id.clone(),
+    )
+    .await?;
+
+    let user_name = user_account_entity
+        .user_name
+        .clone()
+        .ok_or(Error::AccountNotRegistered)?;
+    verify_current_password(&user_account_entity, &password_dto.password)?;
+    if user_account_entity.is_totp_enabled {
+        return Err(Error::TotpAlreadyEnabled);
+    }
+
+    let totp_secret = security::generate_totp_secret();
+    account_database::start_totp_enrollment(
+        database_connection_pool,
+        authenticated_user.user_id,
+        totp_secret.clone(),
+    )
+    .await?;
+
+    Ok(TotpEnrollmentDto {
+        uri: security::get_totp_uri(&totp_secret, &user_name),
+        secret: totp_secret,
+    })
+}
+
+// Recovery codes are returned only once, only their hashes are stored
+pub async fn confirm_totp_enrollment(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    authenticated_user: AuthenticatedUser,
+    confirm_totp_enrollment_dto: ConfirmTotpEnrollmentDto,
+) -> Result<RecoveryCodesDto, Error> {
+    if !SECOND_FACTOR_RATE_LIMITER.try_acquire(&authenticated_user.user_id) {
+        return Err(Error::TooManyRequests);
+    }
+
+    let user_account_entity = account_database::get_user_account(
+        database_connection_pool.clone(),
+        authenticated_user.user_id.clone(),
+    )
+    .await?;
+
+    verify_current_password(&user_account_entity, &confirm_totp_enrollment_dto.password)?;
+    if user_account_entity.is_totp_enabled {
+        return Err(Error::TotpAlreadyEnabled);
+    }
+    let totp_secret = user_account_entity
+        .totp_secret
+        .ok_or(Error::TotpNotEnabled)?;
+
+    let totp_step = security::verify_totp_code(
+        &totp_secret,
+        &confirm_totp_enrollment_dto.code,
+        Utc::now().timestamp(),
+    )
+    .ok_or(Error::WrongTotpCode)?;
+
+    let (recovery_code_entities, recovery_codes): (Vec<RecoveryCodeEntity>, Vec<String>) = (0
+        ..RECOVERY_CODES_COUNT)
+        .map(|_| RecoveryCodeEntity::new(authenticated_user.user_id.clone()))
+        .collect::<Result<Vec<_>, Error>>()?
+        .into_iter()
+        .unzip();
+
+    account_database::enable_totp(
+        database_connection_pool,
+        authenticated_user.user_id,
+        totp_step,
+        recovery_code_entities,
+    )
+    .await?;
+
+    Ok(RecoveryCodesDto { recovery_codes })
+}
+
+// Both password and second factor are required, so that stolen session can't turn it off
+pub async fn disable_totp(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    authenticated_user: AuthenticatedUser,
+    disable_totp_dto: DisableTotpDto,
+) -> Result<(), Error> {
+    let user_account_entity = account_database::get_user_account(
+        database_connection_pool.clone(),
+        authenticated_user.user_id.clone(),
+    )
+    .await?;
+
+    verify_current_password(&user_account_entity, &disable_totp_dto.password)?;
+    verify_second_factor(
+        database_connection_pool.clone(),
+        &user_account_entity,
+        &disable_totp_dto.code,
+    )
+    .await?;
+
+    account_database::disable_totp(database_connection_pool, authenticated_user.user_id).await?;
+
+    Ok(())
+}
+
+// New email address is kept only in token, that is mailed to it, and replaces current one,
+// once token is sent back. Until then password reset codes still go to current verified address,
+// which is told about the change, so that owner notices account being taken over
+pub async fn change_email(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    authenticated_user: AuthenticatedUser,
+    change_email_dto: ChangeEmailDto,
+) -> Result<EmailStatusDto, Error> {
+    if !EMAIL_CHANGE_RATE_LIMITER.try_acquire(&authenticated_user.user_id) {
+        return Err(Error::TooManyRequests);
+    }
+
+    let email = security::validate_email(&change_email_dto.email)?;
+
+    let user_account_entity = account_database::get_user_account(
+        database_connection_pool.clone(),
+        authenticated_user.user_id,
+    )
+    .await?;
+    if user_account_entity.user_name.is_none() {
+        return Err(Error::AccountNotRegistered);
+    }
+    verify_current_password(&user_account_entity, &change_email_dto.password)?;
+
+    let (account_token_entity, account_token) = AccountTokenEntity::new(
+        user_account_entity.user_id.clone(),
+        AccountTokenPurpose::EmailVerification,
+        email.clone(),
+    );
+    account_token_database::insert_account_token(database_connection_pool, account_token_entity)
+        .await?;
+
+    mailer::send_mail(Mail {
+        to: email.clone(),
+        subject: "Verify your email address".to_owned(),
+        body: format!(
+            "Use this code to verify your email address in Machine Notes: {}",
+            account_token
+        ),
+    })
+    .await?;
+
+    if let (Some(current_email), true) = (
+        user_account_entity.email,
+        user_account_entity.is_email_verified,
+    ) {
+        mailer::send_mail(Mail {
+            to: current_email,
+            subject: "Your email address is being changed".to_owned(),
+            body: format!(
+                "Your Machine Notes account is being moved to email address {}.\n\
+                This address stays in use until the new one is verified.\n\
+                If you didn't change it, change your password right away.",
+                email
+            ),
+        })
+        .await?;
+    }
+
+    Ok(EmailStatusDto {
+        email,
+        is_verified: false,
+    })
+}
+
+// Public, so that email address can be verified from any device
+pub async fn verify_email(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    account_token_dto: AccountTokenDto,
+) -> Result<EmailStatusDto, Error> {
+    let account_token_entity = account_token_database::consume_account_token(
+        database_connection_pool.clone(),
+        security::hash_account_token(&account_token_dto.token),
+        AccountTokenPurpose::EmailVerification,
+    )
+    .await?;
+
+    if account_token_entity.is_expired() {
+        return Err(Error::InvalidAccountToken);
+    }
+
+    account_database::verify_email(
+        database_connection_pool,
+        account_token_entity.user_id,
+        account_token_entity.email.clone(),
+    )
+    .await?;
+
+    Ok(EmailStatusDto {
+        email: account_token_entity.email,
+        is_verified: true,
+    })
+}
+
+// Responds the same way whether or not account with such email exists,
+// so that route can't be used to find out registered email addresses
+pub async fn request_password_reset(
+    request: HttpRequest,
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    email_dto: EmailDto,
+) -> Result<(), Error> {
+    if !PASSWORD_RESET_RATE_LIMITER.try_acquire(&get_client_address(&request)) {
+        return Err(Error::TooManyRequests);
+    }
+
+    let email = security::validate_email(&email_dto.email)?;
+
+    let user_account_entity = match account_database::get_user_account_by_verified_email(
+        database_connection_pool.clone(),
+        email,
+    )
+    .await
+    {
+        Ok(user_account_entity) => user_account_entity,
+        Err(Error::UserAccountNotFound) => return Ok(()),
+        Err(error) => return Err(error),
+    };
+    let email = user_account_entity
+        .email
+        .expect("Account with verified email has email");
+
+    let (account_token_entity, account_token) = AccountTokenEntity::new(
+        user_account_entity.user_id,
+        AccountTokenPurpose::PasswordReset,
+        email.clone(),
+    );
+    account_token_database::insert_account_token(database_connection_pool, account_token_entity)
+        .await?;
+
+    mailer::send_mail(Mail {
+        to: email,
+        subject: "Reset your password".to_owned(),
+        body: format!(
+            "Use this code to reset your Machine Notes password: {}\n\
+            If you didn't ask to reset your password, just ignore this mail.",
+            account_token
+        ),
+    })
+    .await
+}
+
+// Every session is revoked, because whoever has signed in before might know old password
+pub async fn reset_password(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    reset_password_dto: ResetPasswordDto,
+) -> Result<(), Error> {
+    // Password is checked first, so that token isn't used up by weak password
+    let password_strength_issues = security::check_password_strength(
+        &reset_password_dto.new_password,
+        &security::PASSWORD_POLICY,
+    );
+    if !password_strength_issues.is_empty() {
+        return Err(Error::WeakPassword(password_strength_issues));
+    }
+
+    let account_token_entity = account_token_database::consume_account_token(
+        database_connection_pool.clone(),
+        security::hash_account_token(&reset_password_dto.token),
+        AccountTokenPurpose::PasswordReset,
+    )
+    .await?;
+
+    if account_token_entity.is_expired() {
+        return Err(Error::InvalidAccountToken);
+    }
+
+    // Code, that was mailed to previous address, can't be used once email is changed
+    let user_account_entity = account_database::get_user_account(
+        database_connection_pool.clone(),
+        account_token_entity.user_id.clone(),
+    )
+    .await?;
+    if !user_account_entity.is_email_verified
+        || user_account_entity.email.as_ref() != Some(&account_token_entity.email)
+    {
+        return Err(Error::InvalidAccountToken);
+    }
+
+    let password_hash_data =
+        security::generate_password_hash(reset_password_dto.new_password.as_bytes())?;
+
+    account_database::update_password(
+        database_connection_pool.clone(),
+        account_token_entity.user_id.clone(),
+        password_hash_data,
+    )
+    .await?;
+
+    let deleted_session_ids =
+        session_database::delete_sessions(database_connection_pool, account_token_entity.user_id)
+            .await?;
+    session_cache::revoke_sessions(deleted_session_ids);
+
+    Ok(())
+}
+
+pub async fn get_sessions(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    authenticated_user: AuthenticatedUser,
+) -> Result<Vec<SessionDto>, Error> {
+    let user_account_entity = account_database::get_user_account(
+        database_connection_pool.clone(),
+        authenticated_user.user_id.clone(),
+    )
+    .await?;
+    delete_expired_sessions(database_connection_pool.clone(), &user_account_entity).await?;
+
+    let session_entities =
+        session_database::get_sessions(database_connection_pool, authenticated_user.user_id)
+            .await?;
+
+    Ok(session_entities
+        .into_iter()
+        .map(|session_entity| {
+            let is_current = session_entity.session_id == authenticated_user.session_id;
+            map_session_entity_to_session_dto(session_entity, is_current)
+        })
+        .collect())
+}
+
+pub async fn delete_session(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    authenticated_user: AuthenticatedUser,
+    session_id: String,
+) -> Result<usize, Error> {
+    let num_deleted = session_database::delete_user_session(
+        database_connection_pool,
+        authenticated_user.user_id,
+        session_id.clone(),
+    )
+    .await?;
+    session_cache::revoke_sessions(vec![session_id]);
+
+    Ok(num_deleted)
+}
+
+pub async fn delete_other_sessions(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    authenticated_user: AuthenticatedUser,
+) -> Result<usize, Error> {
+    let deleted_session_ids = session_database::delete_other_sessions(
+        database_connection_pool,
+        authenticated_user.user_id,
+        authenticated_user.session_id,
+    )
+    .await?;
+    let num_deleted = deleted_session_ids.len();
+    session_cache::revoke_sessions(deleted_session_ids);
+
+    Ok(num_deleted)
+}
+
 async fn sign_up_as_user(
     request: HttpRequest,
     database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    authenticated_user: Option<AuthenticatedUser>,
     credentials_dto: CredentialsDto,
-) -> ProfileDto {
-    println!("Received {}", &credentials_dto.user_name);
-
-    match security::check_password_strength(&credentials_dto.password) {
-        PasswordStrengthIssue::None => {
-            let insertable_user_account_entity = InsertableUserAccountEntity::registered_user(
-                credentials_dto.user_name,
-                credentials_dto.password,
-            );
+) -> Result<ProfileDto, Error> {
+    // Guest signing up is upgraded, so that guest's notes aren't lost
+    if let Some(authenticated_user) = authenticated_user {
+        let user_account_entity = account_database::get_user_account(
+            database_connection_pool.clone(),
+            authenticated_user.user_id.clone(),
+        )
+        .await?;
 
-            let user_account_entity = account_database::insert_user_account(
-                database_connection_pool.clone(),
-                insertable_user_account_entity,
+        if user_account_entity.user_name.is_none() {
+            return upgrade_guest(
+                request,
+                database_connection_pool,
+                authenticated_user,
+                credentials_dto,
             )
             .await;
+        }
+    }
+
+    println!("Received {}", &credentials_dto.user_name);
 
-            user_sucessfully_authorized(request, database_connection_pool, user_account_entity)
-                .await
+    let user_name = security::validate_user_name(&credentials_dto.user_name)?;
+
+    let password_strength_issues =
+        security::check_password_strength(&credentials_dto.password, &security::PASSWORD_POLICY);
+    if !password_strength_issues.is_empty() {
+        return Err(Error::WeakPassword(password_strength_issues));
+    }
+
+    let insertable_user_account_entity =
+        InsertableUserAccountEntity::registered_user(user_name, credentials_dto.password)?;
+
+    let user_account_entity = account_database::insert_user_account(
+        database_connection_pool.clone(),
+        insertable_user_account_entity,
+    )
+    .await?;
+
+    start_session(&request, database_connection_pool, user_account_entity).await
+}
+
+// Guests have no password, so they can't pass this check
+fn verify_current_password(
+    user_account_entity: &UserAccountEntity,
+    password: &str,
+) -> Result<(), Error> {
+    let password_hash = user_account_entity
+        .password_hash
+        .as_ref()
+        .ok_or(Error::WrongCredentials)?;
+
+    if security::verify_password(password.as_bytes(), password_hash)? {
+        Ok(())
+    } else {
+        Err(Error::WrongCredentials)
+    }
+}
+
+// Code is either TOTP code, that can't be used twice, or recovery code, that is deleted once used
+async fn verify_second_factor(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    user_account_entity: &UserAccountEntity,
+    code: &str,
+) -> Result<(), Error> {
+    if !SECOND_FACTOR_RATE_LIMITER.try_acquire(&user_account_entity.user_id) {
+        return Err(Error::TooManyRequests);
+    }
+
+    let totp_secret = match (
+        user_account_entity.is_totp_enabled,
+        &user_account_entity.totp_secret,
+    ) {
+        (true, Some(totp_secret)) => totp_secret,
+        _ => return Err(Error::TotpNotEnabled),
+    };
+
+    if security::is_totp_code(code) {
+        let totp_step = security::verify_totp_code(totp_secret, code, Utc::now().timestamp())
+            .ok_or(Error::WrongTotpCode)?;
+
+        // Code of already used time step is rejected, conditional update still guards
+        // against concurrent requests with the same code
+        if user_account_entity
+            .totp_last_used_step
+            .is_some_and(|totp_last_used_step| totp_step <= totp_last_used_step)
+        {
+            return Err(Error::WrongTotpCode);
         }
-        _ => {
-            panic!("Password is weak")
+
+        return account_database::use_totp_step(
+            database_connection_pool,
+            user_account_entity.user_id.clone(),
+            totp_step,
+        )
+        .await;
+    }
+
+    let recovery_code = security::normalize_recovery_code(code);
+    let recovery_code_entities = account_database::get_recovery_codes(
+        database_connection_pool.clone(),
+        user_account_entity.user_id.clone(),
+    )
+    .await?;
+
+    let mut matching_recovery_code_entity = None;
+    for recovery_code_entity in recovery_code_entities {
+        if security::verify_password(recovery_code.as_bytes(), &recovery_code_entity.code_hash)? {
+            matching_recovery_code_entity = Some(recovery_code_entity);
+            break;
         }
     }
+    let recovery_code_entity = matching_recovery_code_entity.ok_or(Error::WrongTotpCode)?;
+
+    let num_deleted = account_database::delete_recovery_code(
+        database_connection_pool,
+        recovery_code_entity.recovery_code_id,
+    )
+    .await?;
+
+    if num_deleted == 0 {
+        Err(Error::WrongTotpCode)
+    } else {
+        Ok(())
+    }
 }
 
 async fn user_sucessfully_authorized(
     request: HttpRequest,
     database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    authenticated_user: Option<AuthenticatedUser>,
     user_account_entity: UserAccountEntity,
-) -> ProfileDto {
-    // We need to delete user's guest account to avoid zombie user accounts
-    let guest_access_token = security::get_access_token_from_request_headers(request.headers());
-    account_database::delete_user_account(database_connection_pool.clone(), guest_access_token)
-        .await;
+    discard_guest_notes: bool,
+) -> Result<ProfileDto, Error> {
+    let guest_notes_merge_report_dto = match authenticated_user {
+        Some(authenticated_user) => {
+            merge_guest_account(
+                database_connection_pool.clone(),
+                authenticated_user,
+                user_account_entity.user_id.clone(),
+                discard_guest_notes,
+            )
+            .await?
+        }
+        None => None,
+    };
 
-    map_user_account_entity_to_profile_dto(user_account_entity).await
+    let mut profile_dto =
+        start_session(&request, database_connection_pool, user_account_entity).await?;
+    profile_dto.guest_notes_merge_report_dto = guest_notes_merge_report_dto;
+
+    Ok(profile_dto)
+}
+
+// We need to delete user's guest account to avoid zombie user accounts,
+// guest's notes are moved into signed in account first, unless they are discarded.
+// Registered accounts are kept, because their other devices might still be signed in.
+async fn merge_guest_account(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    authenticated_user: AuthenticatedUser,
+    user_id: String,
+    discard_guest_notes: bool,
+) -> Result<Option<GuestNotesMergeReportDto>, Error> {
+    let guest_account_entity = account_database::get_user_account(
+        database_connection_pool.clone(),
+        authenticated_user.user_id,
+    )
+    .await?;
+
+    if guest_account_entity.user_name.is_some() {
+        return Ok(None);
+    }
+
+    let guest_notes_merge_report_dto = if discard_guest_notes {
+        let num_deleted = notes_database::delete_all_notes(
+            database_connection_pool.clone(),
+            guest_account_entity.user_id.clone(),
+        )
+        .await?;
+
+        GuestNotesMergeReportDto {
+            merged_notes: 0,
+            rekeyed_note_dtos: Vec::new(),
+            discarded_notes: num_deleted,
+        }
+    } else {
+        let (num_moved, rekeyed_note_ids) = notes_database::move_notes(
+            database_connection_pool.clone(),
+            guest_account_entity.user_id.clone(),
+            user_id,
+        )
+        .await?;
+
+        GuestNotesMergeReportDto {
+            merged_notes: num_moved,
+            rekeyed_note_dtos: rekeyed_note_ids
+                .into_iter()
+                .map(|(old_note_id, new_note_id)| RekeyedNoteDto {
+                    old_note_id,
+                    new_note_id,
+                })
+                .collect(),
+            discarded_notes: 0,
+        }
+    };
+
+    delete_user_account(database_connection_pool, guest_account_entity.user_id).await?;
+
+    Ok(Some(guest_notes_merge_report_dto))
+}
+
+// Sessions are deleted along with user account, so they have to be revoked
+async fn delete_user_account(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    user_id: String,
+) -> Result<(), Error> {
+    let session_entities =
+        session_database::get_sessions(database_connection_pool.clone(), user_id.clone()).await?;
+
+    account_database::delete_user_account(database_connection_pool, user_id).await?;
+
+    session_cache::revoke_sessions(
+        session_entities
+            .into_iter()
+            .map(|session_entity| session_entity.session_id)
+            .collect(),
+    );
+
+    Ok(())
 }
 
 async fn sign_up_as_guest(
+    request: HttpRequest,
     database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
-) -> ProfileDto {
+) -> Result<ProfileDto, Error> {
     let insertable_user_account_entity = InsertableUserAccountEntity::guest();
 
     let user_account_entity = account_database::insert_user_account(
         database_connection_pool.clone(),
         insertable_user_account_entity,
     )
-    .await;
+    .await?;
 
-    map_user_account_entity_to_profile_dto(user_account_entity).await
+    start_session(&request, database_connection_pool, user_account_entity).await
+}
+
+// Every sign in gets its own session, so that user can be signed in on several devices at once
+async fn start_session(
+    request: &HttpRequest,
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    user_account_entity: UserAccountEntity,
+) -> Result<ProfileDto, Error> {
+    let headers = request.headers();
+    let device_name = headers
+        .get(DEVICE_NAME_HEADER_KEY)
+        .and_then(|header_value| header_value.to_str().ok())
+        .map(str::to_owned);
+    let user_agent = headers
+        .get(USER_AGENT)
+        .and_then(|header_value| header_value.to_str().ok())
+        .map(str::to_owned);
+
+    let (insertable_session_entity, auth_token) = InsertableSessionEntity::new(
+        user_account_entity.user_id.clone(),
+        user_account_entity.user_name.is_none(),
+        device_name,
+        user_agent,
+    )?;
+
+    delete_expired_sessions(database_connection_pool.clone(), &user_account_entity).await?;
+    session_database::insert_session(database_connection_pool, insertable_session_entity).await?;
+
+    Ok(map_user_account_entity_to_profile_dto(user_account_entity, auth_token).await)
+}
+
+async fn delete_expired_sessions(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    user_account_entity: &UserAccountEntity,
+) -> Result<(), Error> {
+    let (created_before, last_used_before) =
+        security::get_session_expiry_bounds(user_account_entity.user_name.is_none());
+
+    session_database::delete_expired_sessions(
+        database_connection_pool,
+        user_account_entity.user_id.clone(),
+        created_before,
+        last_used_before,
+    )
+    .await?;
+
+    Ok(())
 }
 
 async fn map_user_account_entity_to_profile_dto(
     user_account_entity: UserAccountEntity,
+    auth_token: AuthToken,
 ) -> ProfileDto {
-    let auth_token_dto = AuthTokenDto {
-        access_token: user_account_entity.access_token,
-        refresh_token: user_account_entity.refresh_token,
-    };
-
     ProfileDto {
         user_id: user_account_entity.user_id.to_string(),
         user_name: user_account_entity.user_name,
-        auth_token_dto: auth_token_dto,
+        email: user_account_entity.email,
+        is_email_verified: user_account_entity.is_email_verified,
+        auth_token_dto: map_auth_token_to_auth_token_dto(auth_token),
+        guest_notes_merge_report_dto: None,
+    }
+}
+
+// Address of TCP peer is used rather than of forwarding headers, which clients can forge,
+// so behind reverse proxy all clients share proxy's address and its rate limits
+fn get_client_address(request: &HttpRequest) -> String {
+    request
+        .peer_addr()
+        .map(|peer_address| peer_address.ip().to_string())
+        .unwrap_or_default()
+}
+
+fn map_auth_token_to_auth_token_dto(auth_token: AuthToken) -> AuthTokenDto {
+    AuthTokenDto {
+        access_token: auth_token.access_token,
+        refresh_token: auth_token.refresh_token,
+    }
+}
+
+fn map_session_entity_to_session_dto(
+    session_entity: SessionEntity,
+    is_current: bool,
+) -> SessionDto {
+    SessionDto {
+        session_id: session_entity.session_id,
+        device_name: session_entity.device_name,
+        user_agent: session_entity.user_agent,
+        created_at: session_entity.created_at.into(),
+        last_used_at: session_entity.last_used_at.into(),
+        is_current,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        mailer::take_memory_mails,
+        notes_data::NoteContentType,
+        notes_entity::{NoteContentEntity, NoteEntity},
+        postgres_database_connection::establish_database_connection,
+        schema::session,
+        session_cache,
+    };
+    use actix_web::test::TestRequest;
+    use diesel::prelude::*;
+    use diesel::r2d2::{ConnectionManager, Pool};
+    use lazy_static::lazy_static;
+    use std::{collections::HashMap, env, sync::Barrier, thread, time::Duration};
+    use uuid::Uuid;
+
+    const PASSWORD: &str = "Secr3t!pass";
+    const NEW_PASSWORD: &str = "N3w!secret";
+
+    embed_migrations!();
+
+    lazy_static! {
+        static ref TEST_DATABASE_CONNECTION_POOL: Arc<PostgresDatabaseConnectionPool> =
+            get_test_database_connection_pool();
+    }
+
+    // Database tests are ignored by default, so missing database is an error, once they are run
+    fn get_test_database_connection_pool() -> Arc<PostgresDatabaseConnectionPool> {
+        let database_url = env::var("TEST_DATABASE_URL")
+            .expect("TEST_DATABASE_URL must be set to run database tests");
+
+        // Sessions are started with tokens, that need keys
+        for (key, value) in [
+            ("JWT_SECRET", "test_jwt_secret"),
+            (
+                "REFRESH_TOKEN_HASH_SECRET",
+                "test_refresh_token_hash_secret",
+            ),
+        ] {
+            if env::var(key).is_err() {
+                env::set_var(key, value);
+            }
+        }
+
+        let database_connection_pool = Pool::builder()
+            .build(ConnectionManager::new(database_url))
+            .unwrap();
+        embedded_migrations::run(&database_connection_pool.get().unwrap()).unwrap();
+
+        Arc::new(database_connection_pool)
+    }
+
+    // Every test gets its own users, so that tests can run concurrently
+    struct TestUser {
+        database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+        authenticated_user: AuthenticatedUser,
+    }
+
+    impl TestUser {
+        async fn new() -> Self {
+            let user_name = format!("test_{}", &Uuid::new_v4().to_simple().to_string()[..16]);
+            Self::insert(
+                InsertableUserAccountEntity::registered_user(user_name, PASSWORD.to_owned())
+                    .unwrap(),
+            )
+            .await
+        }
+
+        async fn new_guest() -> Self {
+            Self::insert(InsertableUserAccountEntity::guest()).await
+        }
+
+        async fn insert(insertable_user_account_entity: InsertableUserAccountEntity) -> Self {
+            let database_connection_pool = TEST_DATABASE_CONNECTION_POOL.clone();
+            let user_account_entity = account_database::insert_user_account(
+                database_connection_pool.clone(),
+                insertable_user_account_entity,
+            )
+            .await
+            .unwrap();
+
+            TestUser {
+                database_connection_pool,
+                authenticated_user: AuthenticatedUser {
+                    user_id: user_account_entity.user_id,
+                    session_id: Uuid::new_v4().to_string(),
+                },
+            }
+        }
+
+        // Returns new address along with verification token, that was mailed to it
+        async fn change_email(&self) -> (String, String) {
+            let email = format!("{}@example.com", Uuid::new_v4());
+            change_email(
+                self.database_connection_pool.clone(),
+                self.authenticated_user.clone(),
+                ChangeEmailDto {
+                    password: PASSWORD.to_owned(),
+                    email: email.clone(),
+                },
+            )
+            .await
+            .unwrap();
+            let account_token = take_mailed_account_token(&email);
+            (email, account_token)
+        }
+
+        async fn change_and_verify_email(&self) -> String {
+            let (email, account_token) = self.change_email().await;
+            verify_email(
+                self.database_connection_pool.clone(),
+                account_token_dto(&account_token),
+            )
+            .await
+            .unwrap();
+            email
+        }
+
+        async fn insert_note(&self, note_id: &str, content: &str) {
+            let note_entity = NoteEntity {
+                user_id: self.authenticated_user.user_id.clone(),
+                note_id: note_id.to_owned(),
+                date_time_created: SystemTime::now(),
+                date_time_last_edited: SystemTime::now(),
+            };
+            let note_content_entity = NoteContentEntity {
+                user_id: self.authenticated_user.user_id.clone(),
+                note_id: note_id.to_owned(),
+                note_content_id: "content".to_owned(),
+                order_number: 0,
+                content_type: NoteContentType::Text,
+                content: content.to_owned(),
+            };
+            notes_database::insert_note(
+                self.database_connection_pool.clone(),
+                note_entity,
+                vec![note_content_entity],
+            )
+            .await
+            .unwrap();
+        }
+
+        // Maps ids of all notes to their contents
+        async fn get_note_contents(&self) -> HashMap<String, String> {
+            notes_database::get_notes(
+                self.database_connection_pool.clone(),
+                self.authenticated_user.user_id.clone(),
+                100,
+                0,
+            )
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(note_entity, note_content_entities)| {
+                (
+                    note_entity.note_id,
+                    note_content_entities[0].content.clone(),
+                )
+            })
+            .collect()
+        }
+
+        async fn is_deleted(&self) -> bool {
+            matches!(
+                account_database::get_user_account(
+                    self.database_connection_pool.clone(),
+                    self.authenticated_user.user_id.clone(),
+                )
+                .await,
+                Err(Error::UserAccountNotFound)
+            )
+        }
+
+        async fn insert_session(&self) -> String {
+            self.start_session().await.0
+        }
+
+        // Returns id of started session along with its tokens
+        async fn start_session(&self) -> (String, AuthToken) {
+            let (insertable_session_entity, auth_token) = InsertableSessionEntity::new(
+                self.authenticated_user.user_id.clone(),
+                false,
+                None,
+                None,
+            )
+            .unwrap();
+            let session_entity = session_database::insert_session(
+                self.database_connection_pool.clone(),
+                insertable_session_entity,
+            )
+            .await
+            .unwrap();
+            (session_entity.session_id, auth_token)
+        }
+    }
+
+    // Code is the last word of mail's first line
+    fn take_mailed_account_token(email: &str) -> String {
+        let mails = take_memory_mails(email);
+        assert_eq!(mails.len(), 1);
+        mails[0]
+            .body
+            .lines()
+            .next()
+            .and_then(|line| line.split(' ').next_back())
+            .unwrap()
+            .to_owned()
+    }
+
+    fn account_token_dto(account_token: &str) -> AccountTokenDto {
+        AccountTokenDto {
+            token: account_token.to_owned(),
+        }
+    }
+
+    fn reset_password_dto(account_token: &str) -> ResetPasswordDto {
+        ResetPasswordDto {
+            token: account_token.to_owned(),
+            new_password: NEW_PASSWORD.to_owned(),
+        }
+    }
+
+    #[actix_rt::test]
+    #[ignore = "needs database at TEST_DATABASE_URL"]
+    async fn account_token_works_only_once() {
+        let test_user = TestUser::new().await;
+        let (_, account_token) = test_user.change_email().await;
+
+        let email_status_dto = verify_email(
+            test_user.database_connection_pool.clone(),
+            account_token_dto(&account_token),
+        )
+        .await
+        .unwrap();
+        assert!(email_status_dto.is_verified);
+
+        assert!(matches!(
+            verify_email(
+                test_user.database_connection_pool,
+                account_token_dto(&account_token)
+            )
+            .await,
+            Err(Error::InvalidAccountToken)
+        ));
+    }
+
+    #[actix_rt::test]
+    #[ignore = "needs database at TEST_DATABASE_URL"]
+    async fn expired_account_token_is_rejected() {
+        let test_user = TestUser::new().await;
+        let (email, _) = test_user.change_email().await;
+
+        let (mut account_token_entity, account_token) = AccountTokenEntity::new(
+            test_user.authenticated_user.user_id,
+            AccountTokenPurpose::EmailVerification,
+            email,
+        );
+        account_token_entity.expires_at = SystemTime::now() - Duration::from_secs(1);
+        account_token_database::insert_account_token(
+            test_user.database_connection_pool.clone(),
+            account_token_entity,
+        )
+        .await
+        .unwrap();
+
+        assert!(matches!(
+            verify_email(
+                test_user.database_connection_pool,
+                account_token_dto(&account_token)
+            )
+            .await,
+            Err(Error::InvalidAccountToken)
+        ));
+    }
+
+    #[actix_rt::test]
+    #[ignore = "needs database at TEST_DATABASE_URL"]
+    async fn account_token_of_other_purpose_is_rejected() {
+        let test_user = TestUser::new().await;
+        let (_, account_token) = test_user.change_email().await;
+
+        assert!(matches!(
+            reset_password(
+                test_user.database_connection_pool.clone(),
+                reset_password_dto(&account_token)
+            )
+            .await,
+            Err(Error::InvalidAccountToken)
+        ));
+
+        // Token isn't used up by request of other purpose
+        assert!(verify_email(
+            test_user.database_connection_pool,
+            account_token_dto(&account_token)
+        )
+        .await
+        .is_ok());
+    }
+
+    #[actix_rt::test]
+    #[ignore = "needs database at TEST_DATABASE_URL"]
+    async fn password_reset_revokes_all_sessions() {
+        let test_user = TestUser::new().await;
+        let email = test_user.change_and_verify_email().await;
+        let session_ids = [
+            test_user.insert_session().await,
+            test_user.insert_session().await,
+        ];
+
+        request_password_reset(
+            TestRequest::default().to_http_request(),
+            test_user.database_connection_pool.clone(),
+            EmailDto {
+                email: email.clone(),
+            },
+        )
+        .await
+        .unwrap();
+        reset_password(
+            test_user.database_connection_pool.clone(),
+            reset_password_dto(&take_mailed_account_token(&email)),
+        )
+        .await
+        .unwrap();
+
+        let session_entities = session_database::get_sessions(
+            test_user.database_connection_pool,
+            test_user.authenticated_user.user_id,
+        )
+        .await
+        .unwrap();
+        assert!(session_entities.is_empty());
+        for session_id in session_ids {
+            assert_eq!(session_cache::is_session_active(&session_id), Some(false));
+        }
+    }
+
+    #[actix_rt::test]
+    #[ignore = "needs database at TEST_DATABASE_URL"]
+    async fn previous_email_stays_reset_target_until_new_one_is_verified() {
+        let test_user = TestUser::new().await;
+        let email = test_user.change_and_verify_email().await;
+
+        let (new_email, email_verification_token) = test_user.change_email().await;
+        let notification_mails = take_memory_mails(&email);
+        assert_eq!(notification_mails.len(), 1);
+        assert!(notification_mails[0].body.contains(&new_email));
+
+        for email in [&new_email, &email] {
+            request_password_reset(
+                TestRequest::default().to_http_request(),
+                test_user.database_connection_pool.clone(),
+                EmailDto {
+                    email: email.clone(),
+                },
+            )
+            .await
+            .unwrap();
+        }
+        assert!(take_memory_mails(&new_email).is_empty());
+        let password_reset_token = take_mailed_account_token(&email);
+
+        verify_email(
+            test_user.database_connection_pool.clone(),
+            account_token_dto(&email_verification_token),
+        )
+        .await
+        .unwrap();
+        assert!(matches!(
+            reset_password(
+                test_user.database_connection_pool,
+                reset_password_dto(&password_reset_token)
+            )
+            .await,
+            Err(Error::InvalidAccountToken)
+        ));
+    }
+
+    #[actix_rt::test]
+    #[ignore = "needs database at TEST_DATABASE_URL"]
+    async fn expired_sessions_are_deleted_instead_of_being_listed() {
+        let test_user = TestUser::new().await;
+        let session_ids = [
+            test_user.insert_session().await,
+            test_user.insert_session().await,
+            test_user.insert_session().await,
+        ];
+
+        // One session is older than max session age, another one wasn't refreshed for too long
+        let long_ago = SystemTime::now() - Duration::from_secs(365 * 24 * 60 * 60);
+        let database_connection =
+            establish_database_connection(test_user.database_connection_pool.clone()).unwrap();
+        diesel::update(session::table.find(&session_ids[0]))
+            .set(session::created_at.eq(long_ago))
+            .execute(&database_connection)
+            .unwrap();
+        diesel::update(session::table.find(&session_ids[1]))
+            .set(session::last_used_at.eq(long_ago))
+            .execute(&database_connection)
+            .unwrap();
+
+        let session_dtos = get_sessions(
+            test_user.database_connection_pool.clone(),
+            AuthenticatedUser {
+                user_id: test_user.authenticated_user.user_id.clone(),
+                session_id: session_ids[2].clone(),
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(session_dtos.len(), 1);
+        assert!(session_dtos[0].is_current);
+
+        for session_id in &session_ids[..2] {
+            assert!(!session_database::is_session_active(
+                test_user.database_connection_pool.clone(),
+                session_id.clone()
+            )
+            .await
+            .unwrap());
+        }
+    }
+
+    #[actix_rt::test]
+    #[ignore = "needs database at TEST_DATABASE_URL"]
+    async fn reused_refresh_token_revokes_session() {
+        let test_user = TestUser::new().await;
+        let (session_id, auth_token) = test_user.start_session().await;
+
+        let auth_token_dto = refresh_token(
+            test_user.database_connection_pool.clone(),
+            auth_token.refresh_token.clone(),
+        )
+        .await
+        .unwrap();
+        assert!(matches!(
+            refresh_token(
+                test_user.database_connection_pool.clone(),
+                auth_token.refresh_token
+            )
+            .await,
+            Err(Error::RefreshTokenReused)
+        ));
+
+        // Token, that replaced reused one, is revoked along with session
+        assert!(refresh_token(
+            test_user.database_connection_pool.clone(),
+            auth_token_dto.refresh_token
+        )
+        .await
+        .is_err());
+        assert!(!session_database::is_session_active(
+            test_user.database_connection_pool,
+            session_id.clone()
+        )
+        .await
+        .unwrap());
+        assert_eq!(session_cache::is_session_active(&session_id), Some(false));
+    }
+
+    #[actix_rt::test]
+    #[ignore = "needs database at TEST_DATABASE_URL"]
+    async fn concurrent_refreshes_with_same_token_revoke_session() {
+        let test_user = TestUser::new().await;
+        let (session_id, auth_token) = test_user.start_session().await;
+
+        // Every refresh runs on its own thread, so that their queries interleave
+        let barrier = Arc::new(Barrier::new(2));
+        let refresh_threads: Vec<_> = (0..2)
+            .map(|_| {
+                let database_connection_pool = test_user.database_connection_pool.clone();
+                let refresh_token_value = auth_token.refresh_token.clone();
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    barrier.wait();
+                    actix_rt::System::new("refresh_token")
+                        .block_on(refresh_token(database_connection_pool, refresh_token_value))
+                })
+            })
+            .collect();
+        let refresh_results: Vec<_> = refresh_threads
+            .into_iter()
+            .map(|refresh_thread| refresh_thread.join().unwrap())
+            .collect();
+
+        assert_eq!(
+            refresh_results
+                .iter()
+                .filter(|refresh_result| refresh_result.is_ok())
+                .count(),
+            1
+        );
+        assert_eq!(
+            refresh_results
+                .iter()
+                .filter(|refresh_result| matches!(refresh_result, Err(Error::RefreshTokenReused)))
+                .count(),
+            1
+        );
+        assert!(!session_database::is_session_active(
+            test_user.database_connection_pool,
+            session_id
+        )
+        .await
+        .unwrap());
+    }
+
+    #[actix_rt::test]
+    #[ignore = "needs database at TEST_DATABASE_URL"]
+    async fn guest_notes_are_merged_into_signed_in_account() {
+        let test_user = TestUser::new().await;
+        let guest = TestUser::new_guest().await;
+        guest.insert_note("first", "first guest note").await;
+        guest.insert_note("second", "second guest note").await;
+
+        let guest_notes_merge_report_dto = merge_guest_account(
+            test_user.database_connection_pool.clone(),
+            guest.authenticated_user.clone(),
+            test_user.authenticated_user.user_id.clone(),
+            false,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(guest_notes_merge_report_dto.merged_notes, 2);
+        assert!(guest_notes_merge_report_dto.rekeyed_note_dtos.is_empty());
+        assert_eq!(guest_notes_merge_report_dto.discarded_notes, 0);
+        let note_contents = test_user.get_note_contents().await;
+        assert_eq!(note_contents.len(), 2);
+        assert_eq!(note_contents["first"], "first guest note");
+        assert_eq!(note_contents["second"], "second guest note");
+        assert!(guest.is_deleted().await);
+    }
+
+    #[actix_rt::test]
+    #[ignore = "needs database at TEST_DATABASE_URL"]
+    async fn colliding_guest_note_is_merged_with_new_id() {
+        let test_user = TestUser::new().await;
+        test_user.insert_note("shared", "user's note").await;
+        let guest = TestUser::new_guest().await;
+        guest.insert_note("shared", "guest's note").await;
+
+        let guest_notes_merge_report_dto = merge_guest_account(
+            test_user.database_connection_pool.clone(),
+            guest.authenticated_user.clone(),
+            test_user.authenticated_user.user_id.clone(),
+            false,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(guest_notes_merge_report_dto.merged_notes, 1);
+        assert_eq!(guest_notes_merge_report_dto.rekeyed_note_dtos.len(), 1);
+        let rekeyed_note_dto = &guest_notes_merge_report_dto.rekeyed_note_dtos[0];
+        assert_eq!(rekeyed_note_dto.old_note_id, "shared");
+        // Note contents follow rekeyed note, while user's note is kept as it is
+        let note_contents = test_user.get_note_contents().await;
+        assert_eq!(note_contents.len(), 2);
+        assert_eq!(note_contents["shared"], "user's note");
+        assert_eq!(note_contents[&rekeyed_note_dto.new_note_id], "guest's note");
+        assert!(guest.is_deleted().await);
+    }
+
+    #[actix_rt::test]
+    #[ignore = "needs database at TEST_DATABASE_URL"]
+    async fn guest_notes_are_discarded_on_request() {
+        let test_user = TestUser::new().await;
+        test_user.insert_note("own", "user's note").await;
+        let guest = TestUser::new_guest().await;
+        guest.insert_note("first", "first guest note").await;
+        guest.insert_note("second", "second guest note").await;
+
+        let guest_notes_merge_report_dto = merge_guest_account(
+            test_user.database_connection_pool.clone(),
+            guest.authenticated_user.clone(),
+            test_user.authenticated_user.user_id.clone(),
+            true,
+        )
+        .await
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(guest_notes_merge_report_dto.merged_notes, 0);
+        assert!(guest_notes_merge_report_dto.rekeyed_note_dtos.is_empty());
+        assert_eq!(guest_notes_merge_report_dto.discarded_notes, 2);
+        let note_contents = test_user.get_note_contents().await;
+        assert_eq!(note_contents.len(), 1);
+        assert_eq!(note_contents["own"], "user's note");
+        assert!(guest.is_deleted().await);
     }
 }
@@ -0,0 +1,55 @@
+use super::{
+    account_token_entity::AccountTokenEntity,
+    error_data::Error,
+    postgres_database_connection::{establish_database_connection, PostgresDatabaseConnectionPool},
+    schema::account_token,
+    security_data::AccountTokenPurpose,
+};
+use diesel::{prelude::*, result::Error as DieselError};
+use std::sync::Arc;
+
+// Only the latest token of each purpose is valid, previously mailed ones are deleted
+pub async fn insert_account_token(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    account_token_entity: AccountTokenEntity,
+) -> Result<(), Error> {
+    let database_connection = establish_database_connection(database_connection_pool)?;
+
+    database_connection.transaction::<_, DieselError, _>(|| {
+        let delete_source = account_token::table
+            .filter(account_token::user_id.eq(&account_token_entity.user_id))
+            .filter(account_token::purpose.eq(&account_token_entity.purpose));
+        diesel::delete(delete_source).execute(&database_connection)?;
+
+        diesel::insert_into(account_token::table)
+            .values(&account_token_entity)
+            .execute(&database_connection)
+    })?;
+
+    println!("Sucessfully inserted {}", account_token_entity);
+
+    Ok(())
+}
+
+// Token is deleted as it's loaded, so that it can be used only once
+pub async fn consume_account_token(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    token_hash: String,
+    purpose: AccountTokenPurpose,
+) -> Result<AccountTokenEntity, Error> {
+    let database_connection = establish_database_connection(database_connection_pool)?;
+
+    let delete_source = account_token::table
+        .find(token_hash)
+        .filter(account_token::purpose.eq(purpose));
+    let account_token_entity: AccountTokenEntity = diesel::delete(delete_source)
+        .get_result(&database_connection)
+        .map_err(|error| match error {
+            DieselError::NotFound => Error::InvalidAccountToken,
+            _ => Error::from(error),
+        })?;
+
+    println!("Sucessfully consumed {}", account_token_entity);
+
+    Ok(account_token_entity)
+}
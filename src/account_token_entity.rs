@@ -0,0 +1,50 @@
+use super::{schema::account_token, security, security_data::AccountTokenPurpose};
+use diesel::{Insertable, Queryable};
+use std::{
+    fmt::{Display, Formatter},
+    time::SystemTime,
+};
+
+#[derive(Insertable, Queryable)]
+#[table_name = "account_token"]
+pub struct AccountTokenEntity {
+    pub token_hash: String,
+    pub user_id: String,
+    pub purpose: AccountTokenPurpose,
+    pub email: String,
+    pub created_at: SystemTime,
+    pub expires_at: SystemTime,
+}
+
+impl AccountTokenEntity {
+    // Only hash of token is stored, so raw token, that has to be mailed, is returned along with entity
+    pub fn new(user_id: String, purpose: AccountTokenPurpose, email: String) -> (Self, String) {
+        let account_token = security::generate_account_token();
+        let created_at = SystemTime::now();
+        let expires_at = created_at
+            + security::get_account_token_lifetime(&purpose)
+                .to_std()
+                .expect("Account token lifetime is positive");
+
+        let account_token_entity = AccountTokenEntity {
+            token_hash: security::hash_account_token(&account_token),
+            user_id,
+            purpose,
+            email,
+            created_at,
+            expires_at,
+        };
+
+        (account_token_entity, account_token)
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= SystemTime::now()
+    }
+}
+
+impl Display for AccountTokenEntity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?} token of {}", self.purpose, self.user_id)
+    }
+}
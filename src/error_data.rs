@@ -1,9 +1,197 @@
+use super::security_data::{PasswordStrengthIssue, UserNameIssue};
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use diesel::r2d2::PoolError;
+use serde::Serialize;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("JWT token creation error")]
-    JWTTokenCreationError,
+    JWTTokenCreation,
+    #[error("Password hashing error")]
+    PasswordHashing,
     #[error("JWT token decoding error")]
-    JWTTokenDecodingError,
+    JWTTokenDecoding,
+    #[error("JWT token has expired")]
+    JWTTokenExpired,
+    #[error("Access token is missing")]
+    MissingAccessToken,
+    #[error("Wrong user name or password")]
+    WrongCredentials,
+    #[error("Wrong refresh token")]
+    WrongRefreshToken,
+    #[error("Refresh token has expired, user has to sign in again")]
+    RefreshTokenExpired,
+    #[error("Refresh token was already used, all tokens of its session are revoked")]
+    RefreshTokenReused,
+    #[error("Session was revoked")]
+    SessionRevoked,
+    #[error("Session was not found")]
+    SessionNotFound,
+    #[error("User account was not found")]
+    UserAccountNotFound,
+    #[error("Note was not found")]
+    NoteNotFound,
+    #[error("User name is already taken")]
+    UserNameTaken,
+    #[error("Account is already registered")]
+    AccountAlreadyRegistered,
+    #[error("Note already exists")]
+    NoteAlreadyExists,
+    #[error("Note contents have duplicate ids")]
+    DuplicateNoteContentId,
+    #[error("Password is weak")]
+    WeakPassword(Vec<PasswordStrengthIssue>),
+    #[error("User name is invalid")]
+    InvalidUserName(Vec<UserNameIssue>),
+    #[error("Account isn't registered")]
+    AccountNotRegistered,
+    #[error("Two-factor authentication is already enabled")]
+    TotpAlreadyEnabled,
+    #[error("Two-factor authentication isn't enabled")]
+    TotpNotEnabled,
+    #[error("Wrong two-factor authentication code")]
+    WrongTotpCode,
+    #[error("Email address is invalid")]
+    InvalidEmail,
+    #[error("Email address is already taken")]
+    EmailTaken,
+    #[error("Token is wrong, expired or was already used")]
+    InvalidAccountToken,
+    #[error("Mail couldn't be sent")]
+    Mail,
+    #[error("Too many requests, try again later")]
+    TooManyRequests,
+    #[error("Page must not be negative and page size must be positive")]
+    InvalidPagination,
+    #[error("Page size must not be greater than {0}")]
+    PageSizeTooLarge(i32),
+    #[error("Request body or query can't be parsed")]
+    MalformedRequest(String),
+    #[error("Database error")]
+    Database(#[from] diesel::result::Error),
+    #[error("Database connection error")]
+    DatabaseConnection(#[from] PoolError),
+}
+
+impl Error {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::JWTTokenCreation => "jwt_token_creation_error",
+            Error::PasswordHashing => "password_hashing_error",
+            Error::JWTTokenDecoding => "jwt_token_decoding_error",
+            Error::JWTTokenExpired => "jwt_token_expired",
+            Error::MissingAccessToken => "missing_access_token",
+            Error::WrongCredentials => "wrong_credentials",
+            Error::WrongRefreshToken => "wrong_refresh_token",
+            Error::RefreshTokenExpired => "refresh_token_expired",
+            Error::RefreshTokenReused => "refresh_token_reused",
+            Error::SessionRevoked => "session_revoked",
+            Error::SessionNotFound => "session_not_found",
+            Error::UserAccountNotFound => "user_account_not_found",
+            Error::NoteNotFound => "note_not_found",
+            Error::UserNameTaken => "user_name_taken",
+            Error::AccountAlreadyRegistered => "account_already_registered",
+            Error::NoteAlreadyExists => "note_already_exists",
+            Error::DuplicateNoteContentId => "duplicate_note_content_id",
+            Error::WeakPassword(_) => "weak_password",
+            Error::InvalidUserName(_) => "invalid_user_name",
+            Error::AccountNotRegistered => "account_not_registered",
+            Error::TotpAlreadyEnabled => "totp_already_enabled",
+            Error::TotpNotEnabled => "totp_not_enabled",
+            Error::WrongTotpCode => "wrong_totp_code",
+            Error::InvalidEmail => "invalid_email",
+            Error::EmailTaken => "email_taken",
+            Error::InvalidAccountToken => "invalid_account_token",
+            Error::Mail => "mail_error",
+            Error::TooManyRequests => "too_many_requests",
+            Error::InvalidPagination => "invalid_pagination",
+            Error::PageSizeTooLarge(_) => "page_size_too_large",
+            Error::MalformedRequest(_) => "malformed_request",
+            Error::Database(_) => "database_error",
+            Error::DatabaseConnection(_) => "database_connection_error",
+        }
+    }
+
+    fn details(&self) -> Vec<String> {
+        match self {
+            Error::WeakPassword(password_strength_issues) => password_strength_issues
+                .iter()
+                .map(|password_strength_issue| password_strength_issue.code().to_owned())
+                .collect(),
+            Error::InvalidUserName(user_name_issues) => user_name_issues
+                .iter()
+                .map(|user_name_issue| user_name_issue.code().to_owned())
+                .collect(),
+            Error::MalformedRequest(reason) => vec![reason.clone()],
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl ResponseError for Error {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Error::JWTTokenDecoding
+            | Error::JWTTokenExpired
+            | Error::MissingAccessToken
+            | Error::WrongCredentials
+            | Error::WrongRefreshToken
+            | Error::RefreshTokenExpired
+            | Error::RefreshTokenReused
+            | Error::InvalidAccountToken
+            | Error::WrongTotpCode
+            | Error::SessionRevoked => StatusCode::UNAUTHORIZED,
+            Error::SessionNotFound | Error::UserAccountNotFound | Error::NoteNotFound => {
+                StatusCode::NOT_FOUND
+            }
+            Error::UserNameTaken
+            | Error::AccountAlreadyRegistered
+            | Error::EmailTaken
+            | Error::TotpAlreadyEnabled
+            | Error::TotpNotEnabled
+            | Error::NoteAlreadyExists => StatusCode::CONFLICT,
+            Error::WeakPassword(_)
+            | Error::InvalidUserName(_)
+            | Error::InvalidEmail
+            | Error::DuplicateNoteContentId
+            | Error::PageSizeTooLarge(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Error::AccountNotRegistered => StatusCode::FORBIDDEN,
+            Error::InvalidPagination | Error::MalformedRequest(_) => StatusCode::BAD_REQUEST,
+            Error::TooManyRequests => StatusCode::TOO_MANY_REQUESTS,
+            Error::JWTTokenCreation
+            | Error::PasswordHashing
+            | Error::Mail
+            | Error::Database(_)
+            | Error::DatabaseConnection(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        println!("Responding with error: {:?}", self);
+
+        let error_dto = ErrorDto {
+            code: self.code(),
+            message: self.to_string(),
+            details: self.details(),
+        };
+
+        HttpResponse::build(self.status_code()).json(ErrorResponseDto { error_dto })
+    }
+}
+
+#[derive(Serialize)]
+pub struct ErrorResponseDto {
+    #[serde(rename(serialize = "error"))]
+    pub error_dto: ErrorDto,
+}
+
+#[derive(Serialize)]
+pub struct ErrorDto {
+    #[serde(rename(serialize = "code"))]
+    pub code: &'static str,
+    #[serde(rename(serialize = "message"))]
+    pub message: String,
+    #[serde(rename(serialize = "details"), skip_serializing_if = "Vec::is_empty")]
+    pub details: Vec<String>,
 }
@@ -0,0 +1,110 @@
+use super::{
+    jwks_dto::{JwkDto, JwkSetDto},
+    jwt_keys,
+    security_data::{JwtKey, JwtPublicKey},
+};
+use actix_web::{
+    guard::Get,
+    web::{resource, Json},
+    Resource, Responder,
+};
+
+pub const JWKS_PATH: &str = "/.well-known/jwks.json";
+
+// Public, so that other services can verify access tokens without signing secret
+pub fn jwks_resource() -> Resource {
+    resource(JWKS_PATH).guard(Get()).to(get_jwks)
+}
+
+async fn get_jwks() -> impl Responder {
+    let jwk_dtos = jwt_keys::get_public_keys()
+        .iter()
+        .filter_map(|jwt_key| map_jwt_key_to_jwk_dto(jwt_key))
+        .collect();
+
+    Json(JwkSetDto { jwk_dtos })
+}
+
+fn map_jwt_key_to_jwk_dto(jwt_key: &JwtKey) -> Option<JwkDto> {
+    let jwk_dto = match jwt_key.public_key.as_ref()? {
+        JwtPublicKey::Rsa { modulus, exponent } => JwkDto {
+            key_type: "RSA",
+            public_key_use: "sig",
+            algorithm: "RS256",
+            key_id: jwt_key.key_id.clone(),
+            modulus: Some(jwt_keys::encode_base64_url(modulus)),
+            exponent: Some(jwt_keys::encode_base64_url(exponent)),
+            curve: None,
+            public_key: None,
+        },
+        JwtPublicKey::Ed25519 { public_key } => JwkDto {
+            key_type: "OKP",
+            public_key_use: "sig",
+            algorithm: "EdDSA",
+            key_id: jwt_key.key_id.clone(),
+            modulus: None,
+            exponent: None,
+            curve: Some("Ed25519"),
+            public_key: Some(jwt_keys::encode_base64_url(public_key)),
+        },
+    };
+
+    Some(jwk_dto)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{Algorithm, DecodingKey};
+    use serde_json::json;
+
+    fn jwt_key(algorithm: Algorithm, public_key: Option<JwtPublicKey>) -> JwtKey {
+        JwtKey {
+            key_id: "key_id".to_owned(),
+            algorithm,
+            encoding_key: None,
+            decoding_key: DecodingKey::from_secret(b"secret"),
+            public_key,
+        }
+    }
+
+    fn serialize(jwt_key: &JwtKey) -> serde_json::Value {
+        serde_json::to_value(map_jwt_key_to_jwk_dto(jwt_key).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn rsa_key_is_published_with_modulus_and_exponent() {
+        let jwt_key = jwt_key(
+            Algorithm::RS256,
+            Some(JwtPublicKey::Rsa {
+                modulus: vec![0xd2, 0xfc],
+                exponent: vec![1, 0, 1],
+            }),
+        );
+
+        assert_eq!(
+            serialize(&jwt_key),
+            json!({"kty": "RSA", "use": "sig", "alg": "RS256", "kid": "key_id", "n": "0vw", "e": "AQAB"})
+        );
+    }
+
+    #[test]
+    fn ed25519_key_is_published_with_curve_and_public_key() {
+        let jwt_key = jwt_key(
+            Algorithm::EdDSA,
+            Some(JwtPublicKey::Ed25519 {
+                public_key: vec![0xd7, 0x5a, 0x98],
+            }),
+        );
+
+        assert_eq!(
+            serialize(&jwt_key),
+            json!({"kty": "OKP", "use": "sig", "alg": "EdDSA", "kid": "key_id", "crv": "Ed25519", "x": "11qY"})
+        );
+    }
+
+    #[test]
+    fn symmetric_key_is_not_published() {
+        assert!(map_jwt_key_to_jwk_dto(&jwt_key(Algorithm::HS512, None)).is_none());
+    }
+}
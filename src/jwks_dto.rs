@@ -0,0 +1,28 @@
+use serde::Serialize;
+
+#[derive(Serialize)]
+pub struct JwkSetDto {
+    #[serde(rename(serialize = "keys"))]
+    pub jwk_dtos: Vec<JwkDto>,
+}
+
+// Fields are named as in RFC 7517, RSA keys have n and e, Ed25519 keys have crv and x
+#[derive(Serialize)]
+pub struct JwkDto {
+    #[serde(rename(serialize = "kty"))]
+    pub key_type: &'static str,
+    #[serde(rename(serialize = "use"))]
+    pub public_key_use: &'static str,
+    #[serde(rename(serialize = "alg"))]
+    pub algorithm: &'static str,
+    #[serde(rename(serialize = "kid"))]
+    pub key_id: String,
+    #[serde(rename(serialize = "n"), skip_serializing_if = "Option::is_none")]
+    pub modulus: Option<String>,
+    #[serde(rename(serialize = "e"), skip_serializing_if = "Option::is_none")]
+    pub exponent: Option<String>,
+    #[serde(rename(serialize = "crv"), skip_serializing_if = "Option::is_none")]
+    pub curve: Option<&'static str>,
+    #[serde(rename(serialize = "x"), skip_serializing_if = "Option::is_none")]
+    pub public_key: Option<String>,
+}
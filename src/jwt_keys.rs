@@ -0,0 +1,697 @@
+use super::{
+    security_data::{JwtKey, JwtKeyRing, JwtPublicKey},
+    utils::get_env_var_or_default,
+};
+use dotenv::dotenv;
+use jsonwebtoken::{crypto, Algorithm, DecodingKey, EncodingKey};
+use lazy_static::lazy_static;
+use openssl::{
+    pkey::{Id, PKey, Private},
+    rsa::Rsa,
+};
+use rand_core::{OsRng, RngCore};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    env,
+    ffi::OsString,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, Instant, SystemTime},
+};
+use uuid::Uuid;
+
+const DEFAULT_SYMMETRIC_KEY_ID: &str = "default";
+const KEY_PAIR_CHECK_MESSAGE: &[u8] = b"machine_notes_key_pair_check";
+const RSA_KEY_BITS: u32 = 2048;
+const SECRET_BYTES: usize = 64;
+
+// Key ring directory contains "<key id>.private.pem" with "<key id>.public.pem" files
+// for asymmetric keys, "<key id>.secret" files for HS512 keys
+// and "active_key_id" file with id of key, that new tokens are signed with
+const ACTIVE_KEY_ID_FILE_NAME: &str = "active_key_id";
+const PRIVATE_KEY_FILE_SUFFIX: &str = ".private.pem";
+const PUBLIC_KEY_FILE_SUFFIX: &str = ".public.pem";
+const SECRET_FILE_SUFFIX: &str = ".secret";
+
+lazy_static! {
+    pub static ref JWT_KEY_RING: RwLock<JwtKeyRingState> = RwLock::new(load_jwt_key_ring_state());
+    static ref JWT_KEY_RING_RELOAD_INTERVAL: Duration = get_jwt_key_ring_reload_interval();
+}
+
+pub struct JwtKeyRingState {
+    key_ring: Arc<JwtKeyRing>,
+    // Key ring is reloaded only if it comes from directory
+    key_ring_directory: Option<PathBuf>,
+    directory_snapshot: Vec<(OsString, Option<SystemTime>, u64)>,
+    checked_at: Instant,
+}
+
+pub fn get_signing_key() -> Arc<JwtKey> {
+    get_key_ring().active_key.clone()
+}
+
+// Tokens without kid were issued before keys got ids, so they are verified with active key
+pub fn get_verification_key(key_id: Option<&str>) -> Option<Arc<JwtKey>> {
+    let key_ring = get_key_ring();
+
+    match key_id {
+        Some(key_id) => key_ring.keys.get(key_id).cloned(),
+        None => Some(key_ring.active_key.clone()),
+    }
+}
+
+// Symmetric keys are secret, so they are never published
+pub fn get_public_keys() -> Vec<Arc<JwtKey>> {
+    let key_ring = get_key_ring();
+
+    let mut public_keys: Vec<Arc<JwtKey>> = key_ring
+        .keys
+        .values()
+        .filter(|jwt_key| jwt_key.public_key.is_some())
+        .cloned()
+        .collect();
+    public_keys.sort_by(|a, b| a.key_id.cmp(&b.key_id));
+
+    public_keys
+}
+
+pub fn encode_base64_url(bytes: &[u8]) -> String {
+    base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+}
+
+pub fn get_key_ring_directory() -> Option<PathBuf> {
+    dotenv().ok();
+    env::var("JWT_KEY_RING_DIR").ok().map(PathBuf::from)
+}
+
+// New key only becomes verification key, so that it can be published before it's promoted.
+// Returns id of generated key.
+pub fn generate_key(key_ring_directory: &Path, algorithm: Algorithm) -> Result<String, String> {
+    fs::create_dir_all(key_ring_directory)
+        .map_err(|error| format!("Can't create {}: {}", key_ring_directory.display(), error))?;
+
+    match algorithm {
+        Algorithm::HS512 => {
+            let key_id = Uuid::new_v4().to_string();
+            let mut secret = [0u8; SECRET_BYTES];
+            OsRng.fill_bytes(&mut secret);
+
+            write_key_ring_file(
+                key_ring_directory,
+                &format!("{}{}", key_id, SECRET_FILE_SUFFIX),
+                encode_base64_url(&secret).as_bytes(),
+                true,
+            )?;
+
+            Ok(key_id)
+        }
+        Algorithm::RS256 | Algorithm::EdDSA => {
+            let private_key = generate_private_key(algorithm)?;
+            let private_key_pem = private_key
+                .private_key_to_pem_pkcs8()
+                .map_err(|error| error.to_string())?;
+            let public_key_pem = private_key
+                .public_key_to_pem()
+                .map_err(|error| error.to_string())?;
+
+            let jwt_key = create_asymmetric_jwt_key(None, Some(&private_key_pem), &public_key_pem)?;
+
+            // Private key is written first, because key is found by its public key file
+            write_key_ring_file(
+                key_ring_directory,
+                &format!("{}{}", jwt_key.key_id, PRIVATE_KEY_FILE_SUFFIX),
+                &private_key_pem,
+                true,
+            )?;
+            write_key_ring_file(
+                key_ring_directory,
+                &format!("{}{}", jwt_key.key_id, PUBLIC_KEY_FILE_SUFFIX),
+                &public_key_pem,
+                false,
+            )?;
+
+            Ok(jwt_key.key_id)
+        }
+        _ => Err("Algorithm must be one of HS512, RS256, EdDSA".to_owned()),
+    }
+}
+
+// Previously active key stays in key ring as retired one, so its tokens remain valid
+pub fn promote_key(key_ring_directory: &Path, key_id: &str) -> Result<(), String> {
+    let jwt_key = load_key_ring_directory_key(key_ring_directory, key_id)?;
+    if jwt_key.encoding_key.is_none() {
+        return Err(format!("Key {} has no private key", key_id));
+    }
+
+    write_key_ring_file(
+        key_ring_directory,
+        ACTIVE_KEY_ID_FILE_NAME,
+        key_id.as_bytes(),
+        false,
+    )
+}
+
+fn get_key_ring() -> Arc<JwtKeyRing> {
+    {
+        let jwt_key_ring_state = JWT_KEY_RING.read().unwrap();
+        if !is_reload_check_due(&jwt_key_ring_state) {
+            return jwt_key_ring_state.key_ring.clone();
+        }
+    }
+
+    let mut jwt_key_ring_state = JWT_KEY_RING.write().unwrap();
+    // Other thread might have reloaded key ring, while we were waiting for lock
+    if is_reload_check_due(&jwt_key_ring_state) {
+        reload_key_ring_if_changed(&mut jwt_key_ring_state);
+    }
+
+    jwt_key_ring_state.key_ring.clone()
+}
+
+fn is_reload_check_due(jwt_key_ring_state: &JwtKeyRingState) -> bool {
+    jwt_key_ring_state.key_ring_directory.is_some()
+        && jwt_key_ring_state.checked_at.elapsed() >= *JWT_KEY_RING_RELOAD_INTERVAL
+}
+
+// Broken key ring directory doesn't stop server, it keeps using previously loaded keys
+fn reload_key_ring_if_changed(jwt_key_ring_state: &mut JwtKeyRingState) {
+    jwt_key_ring_state.checked_at = Instant::now();
+
+    let key_ring_directory = match &jwt_key_ring_state.key_ring_directory {
+        Some(key_ring_directory) => key_ring_directory.clone(),
+        None => return,
+    };
+
+    let directory_snapshot = match get_directory_snapshot(&key_ring_directory) {
+        Ok(directory_snapshot) => directory_snapshot,
+        Err(error) => {
+            println!("Failed to check JWT key ring: {}", error);
+            return;
+        }
+    };
+    if directory_snapshot == jwt_key_ring_state.directory_snapshot {
+        return;
+    }
+
+    match load_key_ring_directory(&key_ring_directory) {
+        Ok(key_ring) => {
+            println!(
+                "Sucessfully reloaded JWT key ring with {} keys, active key is {}",
+                key_ring.keys.len(),
+                key_ring.active_key.key_id
+            );
+            jwt_key_ring_state.key_ring = Arc::new(key_ring);
+            jwt_key_ring_state.directory_snapshot = directory_snapshot;
+        }
+        Err(error) => println!("Failed to reload JWT key ring: {}", error),
+    }
+}
+
+fn load_jwt_key_ring_state() -> JwtKeyRingState {
+    dotenv().ok();
+
+    match get_key_ring_directory() {
+        Some(key_ring_directory) => {
+            let directory_snapshot = get_directory_snapshot(&key_ring_directory)
+                .unwrap_or_else(|error| panic!("Can't read JWT_KEY_RING_DIR: {}", error));
+            let key_ring = load_key_ring_directory(&key_ring_directory)
+                .unwrap_or_else(|error| panic!("Can't load JWT_KEY_RING_DIR: {}", error));
+
+            JwtKeyRingState {
+                key_ring: Arc::new(key_ring),
+                key_ring_directory: Some(key_ring_directory),
+                directory_snapshot,
+                checked_at: Instant::now(),
+            }
+        }
+        None => {
+            let jwt_key = Arc::new(load_jwt_key());
+            let key_ring = JwtKeyRing {
+                keys: HashMap::from([(jwt_key.key_id.clone(), jwt_key.clone())]),
+                active_key: jwt_key,
+            };
+
+            JwtKeyRingState {
+                key_ring: Arc::new(key_ring),
+                key_ring_directory: None,
+                directory_snapshot: Vec::new(),
+                checked_at: Instant::now(),
+            }
+        }
+    }
+}
+
+fn get_jwt_key_ring_reload_interval() -> Duration {
+    dotenv().ok();
+    Duration::from_secs(get_env_var_or_default("JWT_KEY_RING_RELOAD_SECONDS", 10))
+}
+
+// Single key configured with env variables, used when there is no key ring directory
+fn load_jwt_key() -> JwtKey {
+    let algorithm = get_env_var_or_default("JWT_ALGORITHM", Algorithm::HS512);
+    match algorithm {
+        Algorithm::HS512 => {
+            let secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
+            let key_id = get_env_var_or_default("JWT_KEY_ID", DEFAULT_SYMMETRIC_KEY_ID.to_owned());
+            create_symmetric_jwt_key(key_id, secret.as_bytes())
+        }
+        Algorithm::RS256 | Algorithm::EdDSA => {
+            let private_key_pem = read_key_file("JWT_PRIVATE_KEY_FILE");
+            let public_key_pem = read_key_file("JWT_PUBLIC_KEY_FILE");
+
+            let jwt_key = create_asymmetric_jwt_key(
+                env::var("JWT_KEY_ID").ok(),
+                Some(&private_key_pem),
+                &public_key_pem,
+            )
+            .unwrap_or_else(|error| panic!("Can't load JWT key: {}", error));
+            assert!(
+                jwt_key.algorithm == algorithm,
+                "JWT_PUBLIC_KEY_FILE must match JWT_ALGORITHM"
+            );
+
+            jwt_key
+        }
+        _ => panic!("JWT_ALGORITHM must be one of HS512, RS256, EdDSA"),
+    }
+}
+
+fn read_key_file(key: &str) -> Vec<u8> {
+    let path = env::var(key).unwrap_or_else(|_| panic!("{} must be set", key));
+    fs::read(&path).unwrap_or_else(|error| panic!("Can't read {} {}: {}", key, path, error))
+}
+
+fn load_key_ring_directory(key_ring_directory: &Path) -> Result<JwtKeyRing, String> {
+    let active_key_id = read_key_ring_file(key_ring_directory, ACTIVE_KEY_ID_FILE_NAME)?;
+    let active_key_id = String::from_utf8_lossy(&active_key_id).trim().to_owned();
+
+    let mut keys = HashMap::new();
+    for key_id in get_key_ring_directory_key_ids(key_ring_directory)? {
+        let jwt_key = load_key_ring_directory_key(key_ring_directory, &key_id)?;
+        keys.insert(key_id, Arc::new(jwt_key));
+    }
+
+    let active_key = keys
+        .get(&active_key_id)
+        .cloned()
+        .ok_or(format!("Active key {} was not found", active_key_id))?;
+    if active_key.encoding_key.is_none() {
+        return Err(format!("Active key {} has no private key", active_key_id));
+    }
+
+    Ok(JwtKeyRing { active_key, keys })
+}
+
+fn get_key_ring_directory_key_ids(key_ring_directory: &Path) -> Result<Vec<String>, String> {
+    let directory_entries = fs::read_dir(key_ring_directory)
+        .map_err(|error| format!("Can't read {}: {}", key_ring_directory.display(), error))?;
+
+    let mut key_ids = Vec::new();
+    for directory_entry in directory_entries {
+        let file_name = directory_entry
+            .map_err(|error| error.to_string())?
+            .file_name()
+            .to_string_lossy()
+            .into_owned();
+
+        if let Some(key_id) = file_name
+            .strip_suffix(PUBLIC_KEY_FILE_SUFFIX)
+            .or_else(|| file_name.strip_suffix(SECRET_FILE_SUFFIX))
+        {
+            key_ids.push(key_id.to_owned());
+        }
+    }
+
+    Ok(key_ids)
+}
+
+// Retired asymmetric keys may have only public key file left
+fn load_key_ring_directory_key(key_ring_directory: &Path, key_id: &str) -> Result<JwtKey, String> {
+    let secret_file_name = format!("{}{}", key_id, SECRET_FILE_SUFFIX);
+    if key_ring_directory.join(&secret_file_name).exists() {
+        let secret = read_key_ring_file(key_ring_directory, &secret_file_name)?;
+        return Ok(create_symmetric_jwt_key(
+            key_id.to_owned(),
+            String::from_utf8_lossy(&secret).trim().as_bytes(),
+        ));
+    }
+
+    let public_key_pem = read_key_ring_file(
+        key_ring_directory,
+        &format!("{}{}", key_id, PUBLIC_KEY_FILE_SUFFIX),
+    )?;
+    let private_key_pem = read_key_ring_file(
+        key_ring_directory,
+        &format!("{}{}", key_id, PRIVATE_KEY_FILE_SUFFIX),
+    )
+    .ok();
+
+    create_asymmetric_jwt_key(
+        Some(key_id.to_owned()),
+        private_key_pem.as_deref(),
+        &public_key_pem,
+    )
+    .map_err(|error| format!("Key {} is invalid: {}", key_id, error))
+}
+
+fn read_key_ring_file(key_ring_directory: &Path, file_name: &str) -> Result<Vec<u8>, String> {
+    let path = key_ring_directory.join(file_name);
+    fs::read(&path).map_err(|error| format!("Can't read {}: {}", path.display(), error))
+}
+
+// File is replaced atomically, so that running servers never load half written key
+fn write_key_ring_file(
+    key_ring_directory: &Path,
+    file_name: &str,
+    contents: &[u8],
+    is_private: bool,
+) -> Result<(), String> {
+    let path = key_ring_directory.join(file_name);
+    let temporary_path = key_ring_directory.join(format!(".{}.tmp", file_name));
+
+    let mut open_options = OpenOptions::new();
+    open_options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    if is_private {
+        use std::os::unix::fs::OpenOptionsExt;
+        open_options.mode(0o600);
+    }
+    #[cfg(not(unix))]
+    let _ = is_private;
+
+    open_options
+        .open(&temporary_path)
+        .and_then(|mut file| file.write_all(contents))
+        .and_then(|_| fs::rename(&temporary_path, &path))
+        .map_err(|error| format!("Can't write {}: {}", path.display(), error))
+}
+
+// Modification times and sizes of files tell, whether key ring has to be reloaded
+fn get_directory_snapshot(
+    key_ring_directory: &Path,
+) -> Result<Vec<(OsString, Option<SystemTime>, u64)>, String> {
+    let directory_entries = fs::read_dir(key_ring_directory)
+        .map_err(|error| format!("Can't read {}: {}", key_ring_directory.display(), error))?;
+
+    let mut directory_snapshot = Vec::new();
+    for directory_entry in directory_entries {
+        let directory_entry = directory_entry.map_err(|error| error.to_string())?;
+        let metadata = directory_entry
+            .metadata()
+            .map_err(|error| error.to_string())?;
+        directory_snapshot.push((
+            directory_entry.file_name(),
+            metadata.modified().ok(),
+            metadata.len(),
+        ));
+    }
+    directory_snapshot.sort();
+
+    Ok(directory_snapshot)
+}
+
+fn generate_private_key(algorithm: Algorithm) -> Result<PKey<Private>, String> {
+    let private_key = match algorithm {
+        Algorithm::RS256 => Rsa::generate(RSA_KEY_BITS).and_then(PKey::from_rsa),
+        _ => PKey::generate_ed25519(),
+    };
+
+    private_key.map_err(|error| error.to_string())
+}
+
+fn create_symmetric_jwt_key(key_id: String, secret: &[u8]) -> JwtKey {
+    JwtKey {
+        key_id,
+        algorithm: Algorithm::HS512,
+        encoding_key: Some(EncodingKey::from_secret(secret)),
+        decoding_key: DecodingKey::from_secret(secret),
+        public_key: None,
+    }
+}
+
+// Algorithm is derived from public key type, key id defaults to public key thumbprint
+fn create_asymmetric_jwt_key(
+    key_id: Option<String>,
+    private_key_pem: Option<&[u8]>,
+    public_key_pem: &[u8],
+) -> Result<JwtKey, String> {
+    let public_key = parse_public_key(public_key_pem)?;
+
+    let algorithm = match public_key {
+        JwtPublicKey::Rsa { .. } => Algorithm::RS256,
+        JwtPublicKey::Ed25519 { .. } => Algorithm::EdDSA,
+    };
+
+    let decoding_key = match algorithm {
+        Algorithm::RS256 => DecodingKey::from_rsa_pem(public_key_pem),
+        _ => DecodingKey::from_ed_pem(public_key_pem),
+    }
+    .map_err(|error| format!("Invalid public key: {}", error))?;
+
+    let encoding_key = match private_key_pem {
+        Some(private_key_pem) => {
+            let encoding_key = match algorithm {
+                Algorithm::RS256 => EncodingKey::from_rsa_pem(private_key_pem),
+                _ => EncodingKey::from_ed_pem(private_key_pem),
+            }
+            .map_err(|error| format!("Invalid private key: {}", error))?;
+
+            verify_key_pair(&encoding_key, &decoding_key, algorithm)?;
+
+            Some(encoding_key)
+        }
+        None => None,
+    };
+
+    Ok(JwtKey {
+        key_id: key_id.unwrap_or_else(|| get_key_thumbprint(&public_key)),
+        algorithm,
+        encoding_key,
+        decoding_key,
+        public_key: Some(public_key),
+    })
+}
+
+fn verify_key_pair(
+    encoding_key: &EncodingKey,
+    decoding_key: &DecodingKey,
+    algorithm: Algorithm,
+) -> Result<(), String> {
+    let signature = crypto::sign(KEY_PAIR_CHECK_MESSAGE, encoding_key, algorithm)
+        .map_err(|error| format!("Private key can't sign: {}", error))?;
+    let is_key_pair = crypto::verify(&signature, KEY_PAIR_CHECK_MESSAGE, decoding_key, algorithm)
+        .unwrap_or(false);
+
+    if is_key_pair {
+        Ok(())
+    } else {
+        Err("Private and public keys are not a key pair".to_owned())
+    }
+}
+
+fn parse_public_key(public_key_pem: &[u8]) -> Result<JwtPublicKey, String> {
+    let public_key = PKey::public_key_from_pem(public_key_pem)
+        .map_err(|error| format!("Invalid public key: {}", error))?;
+
+    match public_key.id() {
+        Id::RSA => {
+            let rsa = public_key.rsa().map_err(|error| error.to_string())?;
+            Ok(JwtPublicKey::Rsa {
+                modulus: rsa.n().to_vec(),
+                exponent: rsa.e().to_vec(),
+            })
+        }
+        Id::ED25519 => Ok(JwtPublicKey::Ed25519 {
+            public_key: public_key
+                .raw_public_key()
+                .map_err(|error| error.to_string())?,
+        }),
+        _ => Err("Public key must be RSA or Ed25519 key".to_owned()),
+    }
+}
+
+// JWK thumbprint as in RFC 7638, so that key id stays the same while key isn't changed
+fn get_key_thumbprint(public_key: &JwtPublicKey) -> String {
+    let canonical_jwk = match public_key {
+        JwtPublicKey::Rsa { modulus, exponent } => format!(
+            r#"{{"e":"{}","kty":"RSA","n":"{}"}}"#,
+            encode_base64_url(exponent),
+            encode_base64_url(modulus)
+        ),
+        JwtPublicKey::Ed25519 { public_key } => format!(
+            r#"{{"crv":"Ed25519","kty":"OKP","x":"{}"}}"#,
+            encode_base64_url(public_key)
+        ),
+    };
+
+    encode_base64_url(&Sha256::digest(canonical_jwk.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn load_key_ring_state(key_ring_directory: &Path) -> JwtKeyRingState {
+        JwtKeyRingState {
+            key_ring: Arc::new(load_key_ring_directory(key_ring_directory).unwrap()),
+            key_ring_directory: Some(key_ring_directory.to_owned()),
+            directory_snapshot: get_directory_snapshot(key_ring_directory).unwrap(),
+            checked_at: Instant::now(),
+        }
+    }
+
+    fn private_key_path(key_ring_directory: &Path, key_id: &str) -> PathBuf {
+        key_ring_directory.join(format!("{}{}", key_id, PRIVATE_KEY_FILE_SUFFIX))
+    }
+
+    fn generate_key_pair_pems(algorithm: Algorithm) -> (Vec<u8>, Vec<u8>) {
+        let private_key = generate_private_key(algorithm).unwrap();
+        (
+            private_key.private_key_to_pem_pkcs8().unwrap(),
+            private_key.public_key_to_pem().unwrap(),
+        )
+    }
+
+    #[test]
+    fn generated_key_becomes_active_only_once_promoted() {
+        let key_ring_directory = TempDir::new().unwrap();
+        let first_key_id = generate_key(key_ring_directory.path(), Algorithm::HS512).unwrap();
+
+        // Key ring without active key can't be loaded
+        assert!(load_key_ring_directory(key_ring_directory.path()).is_err());
+
+        promote_key(key_ring_directory.path(), &first_key_id).unwrap();
+        let second_key_id = generate_key(key_ring_directory.path(), Algorithm::EdDSA).unwrap();
+
+        let key_ring = load_key_ring_directory(key_ring_directory.path()).unwrap();
+        assert_eq!(key_ring.active_key.key_id, first_key_id);
+        assert_eq!(key_ring.keys.len(), 2);
+        assert!(key_ring.keys.contains_key(&second_key_id));
+    }
+
+    #[test]
+    fn key_ring_is_reloaded_once_directory_changes() {
+        let key_ring_directory = TempDir::new().unwrap();
+        let first_key_id = generate_key(key_ring_directory.path(), Algorithm::HS512).unwrap();
+        promote_key(key_ring_directory.path(), &first_key_id).unwrap();
+        let mut jwt_key_ring_state = load_key_ring_state(key_ring_directory.path());
+
+        let second_key_id = generate_key(key_ring_directory.path(), Algorithm::EdDSA).unwrap();
+        promote_key(key_ring_directory.path(), &second_key_id).unwrap();
+        reload_key_ring_if_changed(&mut jwt_key_ring_state);
+
+        assert_eq!(jwt_key_ring_state.key_ring.active_key.key_id, second_key_id);
+        assert!(jwt_key_ring_state.key_ring.keys.contains_key(&first_key_id));
+    }
+
+    #[test]
+    fn broken_key_ring_directory_keeps_previous_keys() {
+        let key_ring_directory = TempDir::new().unwrap();
+        let key_id = generate_key(key_ring_directory.path(), Algorithm::HS512).unwrap();
+        promote_key(key_ring_directory.path(), &key_id).unwrap();
+        let mut jwt_key_ring_state = load_key_ring_state(key_ring_directory.path());
+
+        fs::write(
+            key_ring_directory.path().join(ACTIVE_KEY_ID_FILE_NAME),
+            "unknown",
+        )
+        .unwrap();
+        reload_key_ring_if_changed(&mut jwt_key_ring_state);
+
+        assert_eq!(jwt_key_ring_state.key_ring.active_key.key_id, key_id);
+    }
+
+    #[test]
+    fn retired_key_without_private_part_is_found_by_key_id() {
+        let key_ring_directory = TempDir::new().unwrap();
+        let retired_key_id = generate_key(key_ring_directory.path(), Algorithm::EdDSA).unwrap();
+        promote_key(key_ring_directory.path(), &retired_key_id).unwrap();
+        let active_key_id = generate_key(key_ring_directory.path(), Algorithm::EdDSA).unwrap();
+        promote_key(key_ring_directory.path(), &active_key_id).unwrap();
+        fs::remove_file(private_key_path(key_ring_directory.path(), &retired_key_id)).unwrap();
+
+        let key_ring = load_key_ring_directory(key_ring_directory.path()).unwrap();
+        let retired_key = key_ring.keys.get(&retired_key_id).unwrap();
+
+        assert_eq!(key_ring.active_key.key_id, active_key_id);
+        assert!(retired_key.encoding_key.is_none());
+        assert_eq!(retired_key.algorithm, Algorithm::EdDSA);
+    }
+
+    #[test]
+    fn active_key_without_private_part_is_rejected() {
+        let key_ring_directory = TempDir::new().unwrap();
+        let key_id = generate_key(key_ring_directory.path(), Algorithm::EdDSA).unwrap();
+        promote_key(key_ring_directory.path(), &key_id).unwrap();
+        fs::remove_file(private_key_path(key_ring_directory.path(), &key_id)).unwrap();
+
+        assert!(load_key_ring_directory(key_ring_directory.path()).is_err());
+        assert!(promote_key(key_ring_directory.path(), &key_id).is_err());
+    }
+
+    #[test]
+    fn keys_of_different_pairs_are_rejected() {
+        for algorithm in [Algorithm::EdDSA, Algorithm::RS256] {
+            let (private_key_pem, _) = generate_key_pair_pems(algorithm);
+            let (other_private_key_pem, other_public_key_pem) = generate_key_pair_pems(algorithm);
+
+            assert!(
+                create_asymmetric_jwt_key(None, Some(&private_key_pem), &other_public_key_pem)
+                    .is_err()
+            );
+            assert!(create_asymmetric_jwt_key(
+                None,
+                Some(&other_private_key_pem),
+                &other_public_key_pem
+            )
+            .is_ok());
+        }
+    }
+
+    #[test]
+    fn keys_of_different_algorithms_are_rejected() {
+        let (private_key_pem, _) = generate_key_pair_pems(Algorithm::EdDSA);
+        let (_, public_key_pem) = generate_key_pair_pems(Algorithm::RS256);
+
+        assert!(create_asymmetric_jwt_key(None, Some(&private_key_pem), &public_key_pem).is_err());
+    }
+
+    // Test vector from RFC 7638, section 3.1
+    #[test]
+    fn rsa_key_thumbprint_matches_rfc_7638() {
+        let public_key = JwtPublicKey::Rsa {
+            modulus: base64::decode_config(
+                "0vx7agoebGcQSuuPiLJXZptN9nndrQmbXEps2aiAFbWhM78LhWx4cbbfAAtVT86zwu1RK7aPFFxuhDR1L6tSoc_BJECPebWKRXjBZCiFV4n3oknjhMstn64tZ_2W-5JsGY4Hc5n9yBXArwl93lqt7_RN5w6Cf0h4QyQ5v-65YGjQR0_FDW2QvzqY368QQMicAtaSqzs8KJZgnYb9c7d0zgdAZHzu6qMQvRL5hajrn1n91CbOpbISD08qNLyrdkt-bFTWhAI4vMQFh6WeZu0fM4lFd2NcRwr3XPksINHaQ-G_xBniIqbw0Ls1jF44-csFCur-kEgU8awapJzKnqDKgw",
+                base64::URL_SAFE_NO_PAD,
+            )
+            .unwrap(),
+            exponent: base64::decode_config("AQAB", base64::URL_SAFE_NO_PAD).unwrap(),
+        };
+
+        assert_eq!(
+            get_key_thumbprint(&public_key),
+            "NzbLsXh8uDCcd-6MNwXF4W_7noWXFZAfHkxZsRGC9Xs"
+        );
+    }
+
+    // Test vector from RFC 8037, appendix A.3
+    #[test]
+    fn ed25519_key_thumbprint_matches_rfc_8037() {
+        let public_key = JwtPublicKey::Ed25519 {
+            public_key: base64::decode_config(
+                "11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo",
+                base64::URL_SAFE_NO_PAD,
+            )
+            .unwrap(),
+        };
+
+        assert_eq!(
+            get_key_thumbprint(&public_key),
+            "kPrK_qmxVWaYVA9wwBF6Iuo3vVzz7TxHCTwXBygrS4k"
+        );
+    }
+}
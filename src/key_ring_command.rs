@@ -0,0 +1,41 @@
+use super::jwt_keys;
+use jsonwebtoken::Algorithm;
+
+const USAGE: &str = "Usage:
+    machine_notes_server generate-key [EdDSA|RS256|HS512] [--promote]
+    machine_notes_server promote-key <key id>";
+
+// Rotation without downtime: generate key, wait until every server instance reloads key ring
+// and publishes new key, then promote it. Retired keys can be deleted once their tokens expire.
+pub fn run(arguments: &[String]) -> Result<(), String> {
+    let key_ring_directory =
+        jwt_keys::get_key_ring_directory().ok_or("JWT_KEY_RING_DIR must be set")?;
+
+    match arguments {
+        [command, options @ ..] if command == "generate-key" => {
+            let algorithm = match options.iter().find(|option| !option.starts_with("--")) {
+                Some(algorithm) => algorithm
+                    .parse()
+                    .map_err(|_| format!("Unknown algorithm {}", algorithm))?,
+                None => Algorithm::EdDSA,
+            };
+
+            let key_id = jwt_keys::generate_key(&key_ring_directory, algorithm)?;
+            println!("Generated key {}", key_id);
+
+            if options.iter().any(|option| option == "--promote") {
+                jwt_keys::promote_key(&key_ring_directory, &key_id)?;
+                println!("Promoted key {}", key_id);
+            }
+
+            Ok(())
+        }
+        [command, key_id] if command == "promote-key" => {
+            jwt_keys::promote_key(&key_ring_directory, key_id)?;
+            println!("Promoted key {}", key_id);
+
+            Ok(())
+        }
+        _ => Err(USAGE.to_owned()),
+    }
+}
@@ -0,0 +1,173 @@
+use super::error_data::Error;
+use actix_web::web;
+use chrono::Utc;
+use dotenv::dotenv;
+use lazy_static::lazy_static;
+use lettre::{
+    message::Mailbox,
+    transport::smtp::{authentication::Credentials, SmtpTransport},
+    Message, Transport,
+};
+use std::{env, fs, path::PathBuf, sync::Mutex};
+use uuid::Uuid;
+
+const SMTP_MAILER_KIND: &str = "smtp";
+const FILE_MAILER_KIND: &str = "file";
+const MEMORY_MAILER_KIND: &str = "memory";
+
+const STARTTLS_SMTP_SECURITY: &str = "starttls";
+const TLS_SMTP_SECURITY: &str = "tls";
+const NONE_SMTP_SECURITY: &str = "none";
+
+lazy_static! {
+    pub static ref MAILER: Box<dyn Mailer> = get_mailer();
+    static ref MEMORY_MAILBOX: Mutex<Vec<Mail>> = Mutex::new(Vec::new());
+}
+
+#[derive(Clone)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+pub trait Mailer: Send + Sync {
+    fn send(&self, mail: &Mail) -> Result<(), String>;
+}
+
+pub struct SmtpMailer {
+    smtp_transport: SmtpTransport,
+    from: Mailbox,
+}
+
+impl Mailer for SmtpMailer {
+    fn send(&self, mail: &Mail) -> Result<(), String> {
+        let to: Mailbox = mail.to.parse().map_err(|error| format!("{}", error))?;
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to)
+            .subject(&mail.subject)
+            .body(mail.body.clone())
+            .map_err(|error| error.to_string())?;
+
+        self.smtp_transport
+            .send(&message)
+            .map(|_| ())
+            .map_err(|error| error.to_string())
+    }
+}
+
+// Every mail is written to its own file, so that mails can be read without mail server
+pub struct FileMailer {
+    directory: PathBuf,
+}
+
+impl Mailer for FileMailer {
+    fn send(&self, mail: &Mail) -> Result<(), String> {
+        fs::create_dir_all(&self.directory).map_err(|error| error.to_string())?;
+
+        let file_name = format!("{}-{}.txt", Utc::now().timestamp(), Uuid::new_v4());
+        let content = format!(
+            "To: {}\nSubject: {}\n\n{}\n",
+            mail.to, mail.subject, mail.body
+        );
+
+        fs::write(self.directory.join(file_name), content).map_err(|error| error.to_string())
+    }
+}
+
+// Mails are only kept in memory, so that tests can read them
+pub struct MemoryMailer;
+
+impl Mailer for MemoryMailer {
+    fn send(&self, mail: &Mail) -> Result<(), String> {
+        MEMORY_MAILBOX.lock().unwrap().push(mail.clone());
+        Ok(())
+    }
+}
+
+// Returns mails, that memory mailer has sent to address, and forgets them
+#[cfg(test)]
+pub fn take_memory_mails(to: &str) -> Vec<Mail> {
+    let mut memory_mailbox = MEMORY_MAILBOX.lock().unwrap();
+    let (mails, other_mails) = memory_mailbox.drain(..).partition(|mail| mail.to == to);
+    *memory_mailbox = other_mails;
+    mails
+}
+
+// Mailers are blocking, so mail is sent on thread pool
+pub async fn send_mail(mail: Mail) -> Result<(), Error> {
+    web::block(move || MAILER.send(&mail))
+        .await
+        .map_err(|error| {
+            println!("Failed to send mail: {:?}", error);
+            Error::Mail
+        })?;
+
+    println!("Sucessfully sent mail");
+
+    Ok(())
+}
+
+fn get_mailer() -> Box<dyn Mailer> {
+    dotenv().ok();
+    let mailer_kind = env::var("MAILER").unwrap_or_else(|_| get_default_mailer_kind());
+    match mailer_kind.as_str() {
+        SMTP_MAILER_KIND => Box::new(get_smtp_mailer()),
+        FILE_MAILER_KIND => Box::new(FileMailer {
+            directory: PathBuf::from(env::var("MAIL_DIR").unwrap_or_else(|_| "mail".to_owned())),
+        }),
+        MEMORY_MAILER_KIND => Box::new(MemoryMailer),
+        _ => panic!("MAILER has invalid value {}", mailer_kind),
+    }
+}
+
+#[cfg(test)]
+fn get_default_mailer_kind() -> String {
+    MEMORY_MAILER_KIND.to_owned()
+}
+
+// Codes, that are written to files, would never reach users, so mailer has to be chosen explicitly
+#[cfg(not(test))]
+fn get_default_mailer_kind() -> String {
+    panic!(
+        "MAILER must be set, either to {} or to {} or {} for local development",
+        SMTP_MAILER_KIND, FILE_MAILER_KIND, MEMORY_MAILER_KIND
+    )
+}
+
+fn get_smtp_mailer() -> SmtpMailer {
+    let smtp_host = env::var("SMTP_HOST").expect("SMTP_HOST must be set");
+    let smtp_security =
+        env::var("SMTP_SECURITY").unwrap_or_else(|_| STARTTLS_SMTP_SECURITY.to_owned());
+
+    let mut smtp_transport_builder = match smtp_security.as_str() {
+        STARTTLS_SMTP_SECURITY => SmtpTransport::starttls_relay(&smtp_host),
+        TLS_SMTP_SECURITY => SmtpTransport::relay(&smtp_host),
+        NONE_SMTP_SECURITY => Ok(SmtpTransport::builder_dangerous(&smtp_host)),
+        _ => panic!("SMTP_SECURITY has invalid value {}", smtp_security),
+    }
+    .unwrap_or_else(|error| panic!("SMTP_HOST has invalid value {}: {}", smtp_host, error));
+
+    if let Ok(smtp_port) = env::var("SMTP_PORT") {
+        let smtp_port = smtp_port
+            .parse()
+            .unwrap_or_else(|_| panic!("SMTP_PORT has invalid value {}", smtp_port));
+        smtp_transport_builder = smtp_transport_builder.port(smtp_port);
+    }
+    if let (Ok(smtp_user_name), Ok(smtp_password)) =
+        (env::var("SMTP_USER_NAME"), env::var("SMTP_PASSWORD"))
+    {
+        smtp_transport_builder =
+            smtp_transport_builder.credentials(Credentials::new(smtp_user_name, smtp_password));
+    }
+
+    let mail_from = env::var("MAIL_FROM").expect("MAIL_FROM must be set");
+
+    SmtpMailer {
+        smtp_transport: smtp_transport_builder.build(),
+        from: mail_from
+            .parse()
+            .unwrap_or_else(|_| panic!("MAIL_FROM has invalid value {}", mail_from)),
+    }
+}
@@ -1,24 +1,45 @@
 #[macro_use]
 extern crate diesel;
+#[cfg(test)]
+#[macro_use]
+extern crate diesel_migrations;
 
 mod account_api;
 mod account_database;
 mod account_dto;
 mod account_entity;
 mod account_interaction;
+mod account_token_database;
+mod account_token_entity;
 mod error_data;
+mod jwks_api;
+mod jwks_dto;
+mod jwt_keys;
+mod key_ring_command;
+mod mailer;
 mod middleware;
 mod notes_api;
 mod notes_data;
+mod notes_database;
+mod notes_entity;
+mod notes_interaction;
 mod postgres_database_connection;
+mod rate_limiter;
 mod schema;
 mod security;
 mod security_data;
+mod session_cache;
+mod session_database;
+mod session_entity;
 mod utils;
 
-use actix_web::{App, HttpServer};
-use actix_web_httpauth::middleware::HttpAuthentication;
+use actix_web::{
+    web::{JsonConfig, QueryConfig},
+    App, HttpServer,
+};
+use error_data::Error;
 use openssl::ssl::{SslAcceptor, SslAcceptorBuilder, SslFiletype, SslMethod};
+use std::{env, process};
 
 const LOCALHOST_WITH_PORT: &str = "localhost:8080";
 
@@ -26,17 +47,45 @@ const LOCALHOST_WITH_PORT: &str = "localhost:8080";
 async fn main() -> std::io::Result<()> {
     // tbd create auth middleware
 
+    // Key ring commands are run instead of server
+    let arguments: Vec<String> = env::args().skip(1).collect();
+    if !arguments.is_empty() {
+        if let Err(error) = key_ring_command::run(&arguments) {
+            eprintln!("{}", error);
+            process::exit(1);
+        }
+        return Ok(());
+    }
+
     // let ssl_acceptor_builder = get_ssl_acceptor_builder();
 
+    // Load password policy with its denylist at startup rather than on first sign up
+    lazy_static::initialize(&security::PASSWORD_POLICY);
+    // Fail fast on misconfigured signing key
+    lazy_static::initialize(&jwt_keys::JWT_KEY_RING);
+    // Fail fast on missing refresh token hash secret rather than on first sign up
+    lazy_static::initialize(&security::REFRESH_TOKEN_HASH_SECRET);
+    // Fail fast on misconfigured mailer
+    lazy_static::initialize(&mailer::MAILER);
+
     let account_database_connection_pool =
         postgres_database_connection::get_database_connection_pool();
 
     HttpServer::new(move || {
         App::new()
-            .wrap(HttpAuthentication::bearer(middleware::bearer_auth_validator))
             .data(account_database_connection_pool.clone())
+            // Extractor errors get the same error response as any other error
+            .app_data(
+                JsonConfig::default()
+                    .error_handler(|error, _| Error::MalformedRequest(error.to_string()).into()),
+            )
+            .app_data(
+                QueryConfig::default()
+                    .error_handler(|error, _| Error::MalformedRequest(error.to_string()).into()),
+            )
             .service(account_api::account_v1_scope())
             .service(notes_api::notes_v1_scope())
+            .service(jwks_api::jwks_resource())
     })
     .bind(LOCALHOST_WITH_PORT)?
     // .bind_openssl(LOCALHOST_WITH_PORT, ssl_acceptor_builder)?
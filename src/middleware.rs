@@ -1,31 +1,102 @@
-use super::{account_api, security};
-use actix_web::{dev::ServiceRequest, Error};
-use actix_web_httpauth::extractors::{
-    bearer::{BearerAuth, Config},
-    AuthenticationError,
+use super::{
+    error_data::Error,
+    postgres_database_connection::PostgresDatabaseConnectionPool,
+    security,
+    security_data::{AuthenticatedUser, TokenType},
+    session_cache, session_database,
 };
+use actix_web::{dev::ServiceRequest, http::header::Header, web::Data, HttpMessage};
+use actix_web_httpauth::{
+    extractors::AuthExtractor,
+    headers::authorization::{Authorization, Bearer},
+};
+use std::future::{ready, Ready};
+
+// Bearer credentials of public routes, where they are optional, e.g. guest signing up
+pub struct OptionalBearerAuth(Option<Bearer>);
+
+impl AuthExtractor for OptionalBearerAuth {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_service_request(service_request: &ServiceRequest) -> Self::Future {
+        let bearer = Authorization::<Bearer>::parse(service_request)
+            .ok()
+            .map(|authorization| authorization.into_scheme());
+
+        ready(Ok(OptionalBearerAuth(bearer)))
+    }
+}
 
+// Wraps private routes, which can't be accessed without valid access token.
+// Bearer credentials are extracted optionally, so that missing token gets the same
+// error response as any other rejected one
 pub async fn bearer_auth_validator(
     service_request: ServiceRequest,
-    credentials: BearerAuth,
-) -> Result<ServiceRequest, Error> {
-    let config = service_request
-        .app_data::<Config>()
-        .map(|data| data.clone())
-        .unwrap_or_else(Default::default);
-
-    let path = service_request.path();
-
-    println!("{}:{}", service_request.method().as_str(), &path);
-
-    if path.ends_with(account_api::SIGN_UP_PATH)
-        || path.ends_with(account_api::SIGN_IN_PATH)
-        || path.ends_with(account_api::REFRESH_TOKEN_PATH)
-    {
-        Ok(service_request)
-    } else if security::verify_jwt(credentials.token()) {
-        Ok(service_request)
+    credentials: OptionalBearerAuth,
+) -> Result<ServiceRequest, actix_web::Error> {
+    println!(
+        "{}:{}",
+        service_request.method().as_str(),
+        service_request.path()
+    );
+
+    let bearer = credentials.0.ok_or(Error::MissingAccessToken)?;
+    let authenticated_user = verify_access_token(&service_request, bearer.token()).await?;
+    service_request.extensions_mut().insert(authenticated_user);
+
+    Ok(service_request)
+}
+
+// Wraps public routes, which still get authenticated user, if access token is valid
+pub async fn optional_bearer_auth_validator(
+    service_request: ServiceRequest,
+    credentials: OptionalBearerAuth,
+) -> Result<ServiceRequest, actix_web::Error> {
+    println!(
+        "{}:{}",
+        service_request.method().as_str(),
+        service_request.path()
+    );
+
+    if let Some(bearer) = credentials.0 {
+        if let Ok(authenticated_user) = verify_access_token(&service_request, bearer.token()).await
+        {
+            service_request.extensions_mut().insert(authenticated_user);
+        }
+    }
+
+    Ok(service_request)
+}
+
+// Access token is valid only while its session is not revoked.
+// Session state is cached, so that we don't hit database on every request.
+async fn verify_access_token(
+    service_request: &ServiceRequest,
+    access_token: &str,
+) -> Result<AuthenticatedUser, Error> {
+    let claims = security::decode_jwt(access_token, TokenType::Access)?;
+
+    let is_session_active = match session_cache::is_session_active(&claims.sid) {
+        Some(is_session_active) => is_session_active,
+        None => {
+            let database_connection_pool = service_request
+                .app_data::<Data<PostgresDatabaseConnectionPool>>()
+                .expect("Database connection pool is registered as app data")
+                .clone()
+                .into_inner();
+
+            let is_session_active =
+                session_database::is_session_active(database_connection_pool, claims.sid.clone())
+                    .await?;
+            session_cache::put_session_state(claims.sid.clone(), is_session_active);
+            is_session_active
+        }
+    };
+
+    if is_session_active {
+        Ok(AuthenticatedUser::from(claims))
     } else {
-        Err(AuthenticationError::from(config).into())
+        Err(Error::SessionRevoked)
     }
 }
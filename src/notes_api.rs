@@ -1,76 +1,123 @@
-use super::notes_data::*;
+use super::{
+    error_data::Error,
+    middleware,
+    notes_data::{Note, PaginationInfo},
+    notes_interaction,
+    postgres_database_connection::PostgresDatabaseConnectionPool,
+    security_data::AuthenticatedUser,
+};
 use actix_web::{
-    delete, get,
-    web::{scope, Json, Query},
-    HttpResponse, Responder, Scope,
+    delete,
+    dev::HttpServiceFactory,
+    get, post, put,
+    web::{scope, Data, Json, Path, Query},
+    HttpResponse, Responder,
 };
-use rand::Rng;
-
-const DUMMY_NOTE_TEXT: &str =
-    "Заметка с очень длинным названием, которое не поместилось вот чуть-чуть\n
-И был этот текст крайне странным, и длина его превышала все границы адекватности\n
-В общем:\n
-Побывал я тут в Европе и повидал множество дивных вещей: архитектуру, людей";
+use actix_web_httpauth::middleware::HttpAuthentication;
 
-pub fn notes_v1_scope() -> Scope {
+// All notes routes are private
+pub fn notes_v1_scope() -> impl HttpServiceFactory {
     scope("v1/notes")
+        .wrap(HttpAuthentication::with_fn(
+            middleware::bearer_auth_validator,
+        ))
         .service(get_notes)
+        .service(get_note)
+        .service(create_note)
+        .service(update_note)
+        .service(delete_note)
         .service(delete_all_notes)
 }
 
 #[get("/")]
-async fn get_notes(pagination_info: Query<PaginationInfo>) -> impl Responder {
-    let notes = create_dummy_notes(pagination_info.page_size);
-    Json(notes)
-}
+async fn get_notes(
+    database_connection_pool: Data<PostgresDatabaseConnectionPool>,
+    authenticated_user: AuthenticatedUser,
+    pagination_info: Query<PaginationInfo>,
+) -> Result<impl Responder, Error> {
+    let notes = notes_interaction::get_notes(
+        database_connection_pool.into_inner(),
+        authenticated_user,
+        pagination_info.into_inner(),
+    )
+    .await?;
 
-#[delete("")]
-async fn delete_all_notes() -> impl Responder {
-    HttpResponse::Ok()
+    Ok(Json(notes))
 }
 
-fn create_dummy_notes(items_amount: i32) -> Vec<Note> {
-    let mut notes_vec = Vec::new();
+#[get("/{note_id}")]
+async fn get_note(
+    database_connection_pool: Data<PostgresDatabaseConnectionPool>,
+    authenticated_user: AuthenticatedUser,
+    note_id: Path<String>,
+) -> Result<impl Responder, Error> {
+    let note = notes_interaction::get_note(
+        database_connection_pool.into_inner(),
+        authenticated_user,
+        note_id.into_inner(),
+    )
+    .await?;
+
+    Ok(Json(note))
+}
 
-    let mut random_generator = rand::thread_rng();
+#[post("")]
+async fn create_note(
+    database_connection_pool: Data<PostgresDatabaseConnectionPool>,
+    authenticated_user: AuthenticatedUser,
+    note: Json<Note>,
+) -> Result<impl Responder, Error> {
+    let note = notes_interaction::create_note(
+        database_connection_pool.into_inner(),
+        authenticated_user,
+        note.into_inner(),
+    )
+    .await?;
 
-    for id in 0..items_amount {
-        let mode = random_generator.gen_range(0..=2);
+    Ok(Json(note))
+}
 
-        notes_vec.push(create_dummy_note(id.to_string(), mode))
-    }
+#[put("/{note_id}")]
+async fn update_note(
+    database_connection_pool: Data<PostgresDatabaseConnectionPool>,
+    authenticated_user: AuthenticatedUser,
+    note_id: Path<String>,
+    note: Json<Note>,
+) -> Result<impl Responder, Error> {
+    let note = notes_interaction::update_note(
+        database_connection_pool.into_inner(),
+        authenticated_user,
+        note_id.into_inner(),
+        note.into_inner(),
+    )
+    .await?;
 
-    return notes_vec;
+    Ok(Json(note))
 }
 
-fn create_dummy_note(id: String, mode: i32) -> Note {
-    let note_content_order_number = 1;
-    let note_content_id = format!("{}_{}", id, note_content_order_number);
-    let date_time_created = DateTime {};
-    let date_time_last_edited = DateTime {};
+#[delete("/{note_id}")]
+async fn delete_note(
+    database_connection_pool: Data<PostgresDatabaseConnectionPool>,
+    authenticated_user: AuthenticatedUser,
+    note_id: Path<String>,
+) -> Result<impl Responder, Error> {
+    notes_interaction::delete_note(
+        database_connection_pool.into_inner(),
+        authenticated_user,
+        note_id.into_inner(),
+    )
+    .await?;
 
-    return Note {
-        id,
-        date_time_created,
-        date_time_last_edited,
-        note_content: {
-            let mut note_content_vec: Vec<NoteContent> = Vec::new();
-            note_content_vec.push(match mode {
-                0 => NoteContent::Text {
-                    id: note_content_id,
-                    content: String::from(DUMMY_NOTE_TEXT),
-                },
-                1 => NoteContent::Image {
-                    id: note_content_id,
-                    content_url: String::from(""),
-                },
+    Ok(HttpResponse::Ok())
+}
+
+#[delete("")]
+async fn delete_all_notes(
+    database_connection_pool: Data<PostgresDatabaseConnectionPool>,
+    authenticated_user: AuthenticatedUser,
+) -> Result<impl Responder, Error> {
+    notes_interaction::delete_all_notes(database_connection_pool.into_inner(), authenticated_user)
+        .await?;
 
-                _ => NoteContent::Audio {
-                    id: note_content_id,
-                    content_url: String::from(""),
-                },
-            });
-            note_content_vec
-        },
-    };
+    Ok(HttpResponse::Ok())
 }
@@ -1,4 +1,14 @@
+use chrono::{DateTime, Utc};
+use diesel::{
+    deserialize,
+    pg::Pg,
+    serialize::{self, Output},
+    sql_types::Text,
+    types::{FromSql, IsNull, ToSql},
+    AsExpression, FromSqlRow,
+};
 use serde::{Deserialize, Serialize};
+use std::io::Write;
 
 #[derive(Deserialize)]
 pub struct PaginationInfo {
@@ -7,43 +17,70 @@ pub struct PaginationInfo {
     pub page: i32,
 }
 
-#[derive(Serialize)]
+#[derive(Deserialize, Serialize)]
 pub struct Note {
-    #[serde(rename(serialize = "id"))]
+    #[serde(rename = "id")]
     pub id: String,
-    #[serde(rename(serialize = "dateTimeCreated"))]
-    pub date_time_created: DateTime,
-    #[serde(rename(serialize = "dateTimeLastEdited"))]
-    pub date_time_last_edited: DateTime,
-    #[serde(rename(serialize = "noteContent"))]
+    #[serde(rename = "dateTimeCreated")]
+    pub date_time_created: DateTime<Utc>,
+    #[serde(rename = "dateTimeLastEdited")]
+    pub date_time_last_edited: DateTime<Utc>,
+    #[serde(rename = "noteContent")]
     pub note_content: Vec<NoteContent>,
 }
 
-#[derive(Serialize)]
-pub struct DateTime {}
-
-#[derive(Serialize)]
+#[derive(Deserialize, Serialize)]
 pub enum NoteContent {
-    #[serde(rename(serialize = "text"))]
+    #[serde(rename = "text")]
     Text {
-        #[serde(rename(serialize = "id"))]
+        #[serde(rename = "id")]
         id: String,
-        #[serde(rename(serialize = "content"))]
+        #[serde(rename = "content")]
         content: String,
     },
-    #[serde(rename(serialize = "image"))]
+    #[serde(rename = "image")]
     Image {
-        #[serde(rename(serialize = "id"))]
+        #[serde(rename = "id")]
         id: String,
-        #[serde(rename(serialize = "contentUrl"))]
+        #[serde(rename = "contentUrl")]
         content_url: String,
     },
 
-    #[serde(rename(serialize = "audio"))]
+    #[serde(rename = "audio")]
     Audio {
-        #[serde(rename(serialize = "id"))]
+        #[serde(rename = "id")]
         id: String,
-        #[serde(rename(serialize = "contentUrl"))]
+        #[serde(rename = "contentUrl")]
         content_url: String,
     },
 }
+
+#[derive(AsExpression, Debug, FromSqlRow)]
+#[sql_type = "Text"]
+pub enum NoteContentType {
+    Text,
+    Image,
+    Audio,
+}
+
+impl ToSql<Text, Pg> for NoteContentType {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        match *self {
+            NoteContentType::Text => out.write_all(b"text")?,
+            NoteContentType::Image => out.write_all(b"image")?,
+            NoteContentType::Audio => out.write_all(b"audio")?,
+        }
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, Pg> for NoteContentType {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        match not_none!(bytes) {
+            b"text" => Ok(NoteContentType::Text),
+            b"image" => Ok(NoteContentType::Image),
+            b"audio" => Ok(NoteContentType::Audio),
+            _ => Err("Unrecognized enum variant".into()),
+        }
+    }
+}
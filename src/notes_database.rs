@@ -0,0 +1,251 @@
+use super::{
+    error_data::Error,
+    notes_entity::{NoteContentEntity, NoteEntity},
+    postgres_database_connection::{establish_database_connection, PostgresDatabaseConnectionPool},
+    schema::{note, note_content},
+};
+use diesel::{
+    prelude::*,
+    result::{DatabaseErrorKind, Error as DieselError},
+};
+use std::{collections::HashMap, sync::Arc};
+use uuid::Uuid;
+
+// Primary key constraints, that Postgres names by default
+const NOTE_PRIMARY_KEY_CONSTRAINT: &str = "note_pkey";
+const NOTE_CONTENT_PRIMARY_KEY_CONSTRAINT: &str = "note_content_pkey";
+
+pub async fn get_notes(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    user_id: String,
+    page_size: i64,
+    page: i64,
+) -> Result<Vec<(NoteEntity, Vec<NoteContentEntity>)>, Error> {
+    let database_connection = establish_database_connection(database_connection_pool)?;
+
+    let note_entities: Vec<NoteEntity> = note::table
+        .filter(note::user_id.eq(&user_id))
+        .order(note::date_time_last_edited.desc())
+        .limit(page_size)
+        .offset(page * page_size)
+        .load(&database_connection)?;
+
+    let note_ids: Vec<&String> = note_entities
+        .iter()
+        .map(|note_entity| &note_entity.note_id)
+        .collect();
+
+    let note_content_entities: Vec<NoteContentEntity> = note_content::table
+        .filter(note_content::user_id.eq(&user_id))
+        .filter(note_content::note_id.eq_any(note_ids))
+        .order(note_content::order_number.asc())
+        .load(&database_connection)?;
+
+    println!("Sucessfully loaded {} notes", note_entities.len());
+
+    let mut note_content_entities_by_note_id: HashMap<String, Vec<NoteContentEntity>> =
+        HashMap::new();
+    for note_content_entity in note_content_entities {
+        note_content_entities_by_note_id
+            .entry(note_content_entity.note_id.clone())
+            .or_default()
+            .push(note_content_entity);
+    }
+
+    Ok(note_entities
+        .into_iter()
+        .map(|note_entity| {
+            let note_content_entities = note_content_entities_by_note_id
+                .remove(&note_entity.note_id)
+                .unwrap_or_default();
+            (note_entity, note_content_entities)
+        })
+        .collect())
+}
+
+pub async fn get_note(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    user_id: String,
+    note_id: String,
+) -> Result<(NoteEntity, Vec<NoteContentEntity>), Error> {
+    let database_connection = establish_database_connection(database_connection_pool)?;
+
+    let note_entity: NoteEntity = note::table
+        .find((&user_id, &note_id))
+        .first(&database_connection)
+        .map_err(map_not_found_error)?;
+
+    let note_content_entities = note_content::table
+        .filter(note_content::user_id.eq(&user_id))
+        .filter(note_content::note_id.eq(&note_id))
+        .order(note_content::order_number.asc())
+        .load(&database_connection)?;
+
+    println!("Sucessfully loaded note {}", note_entity);
+
+    Ok((note_entity, note_content_entities))
+}
+
+pub async fn insert_note(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    note_entity: NoteEntity,
+    note_content_entities: Vec<NoteContentEntity>,
+) -> Result<(NoteEntity, Vec<NoteContentEntity>), Error> {
+    let database_connection = establish_database_connection(database_connection_pool)?;
+
+    let inserted_note = database_connection
+        .transaction::<_, diesel::result::Error, _>(|| {
+            let note_entity: NoteEntity = diesel::insert_into(note::table)
+                .values(&note_entity)
+                .get_result(&database_connection)?;
+
+            let note_content_entities = diesel::insert_into(note_content::table)
+                .values(&note_content_entities)
+                .get_results(&database_connection)?;
+
+            Ok((note_entity, note_content_entities))
+        })
+        .map_err(map_unique_violation_error)?;
+
+    println!("Sucessfully inserted note {}", inserted_note.0);
+
+    Ok(inserted_note)
+}
+
+pub async fn update_note(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    note_entity: NoteEntity,
+    note_content_entities: Vec<NoteContentEntity>,
+) -> Result<(NoteEntity, Vec<NoteContentEntity>), Error> {
+    let database_connection = establish_database_connection(database_connection_pool)?;
+
+    let updated_note = database_connection
+        .transaction::<_, diesel::result::Error, _>(|| {
+            let source = note::table.find((&note_entity.user_id, &note_entity.note_id));
+            let note_entity: NoteEntity = diesel::update(source)
+                .set((
+                    (note::date_time_created.eq(note_entity.date_time_created)),
+                    (note::date_time_last_edited.eq(note_entity.date_time_last_edited)),
+                ))
+                .get_result(&database_connection)?;
+
+            let delete_source = note_content::table
+                .filter(note_content::user_id.eq(&note_entity.user_id))
+                .filter(note_content::note_id.eq(&note_entity.note_id));
+            diesel::delete(delete_source).execute(&database_connection)?;
+
+            let note_content_entities = diesel::insert_into(note_content::table)
+                .values(&note_content_entities)
+                .get_results(&database_connection)?;
+
+            Ok((note_entity, note_content_entities))
+        })
+        .map_err(|error| match error {
+            DieselError::NotFound => Error::NoteNotFound,
+            _ => map_unique_violation_error(error),
+        })?;
+
+    println!("Sucessfully updated note {}", updated_note.0);
+
+    Ok(updated_note)
+}
+
+// Notes, which ids are already taken in target account, get new ids,
+// note contents follow their notes with ON UPDATE CASCADE.
+// Returns number of moved notes along with old and new ids of rekeyed ones
+pub async fn move_notes(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    from_user_id: String,
+    to_user_id: String,
+) -> Result<(usize, Vec<(String, String)>), Error> {
+    let database_connection = establish_database_connection(database_connection_pool)?;
+
+    let moved_notes = database_connection.transaction::<_, diesel::result::Error, _>(|| {
+        let from_note_ids: Vec<String> = note::table
+            .filter(note::user_id.eq(&from_user_id))
+            .select(note::note_id)
+            .load(&database_connection)?;
+        let colliding_note_ids: Vec<String> = note::table
+            .filter(note::user_id.eq(&to_user_id))
+            .filter(note::note_id.eq_any(&from_note_ids))
+            .select(note::note_id)
+            .load(&database_connection)?;
+
+        let mut rekeyed_note_ids = Vec::with_capacity(colliding_note_ids.len());
+        for old_note_id in colliding_note_ids {
+            let new_note_id = Uuid::new_v4().to_string();
+            diesel::update(note::table.find((&from_user_id, &old_note_id)))
+                .set(note::note_id.eq(&new_note_id))
+                .execute(&database_connection)?;
+            rekeyed_note_ids.push((old_note_id, new_note_id));
+        }
+
+        let num_moved = diesel::update(note::table.filter(note::user_id.eq(&from_user_id)))
+            .set(note::user_id.eq(&to_user_id))
+            .execute(&database_connection)?;
+
+        Ok((num_moved, rekeyed_note_ids))
+    })?;
+
+    println!(
+        "Sucessfully moved {} notes, {} of them got new ids",
+        moved_notes.0,
+        moved_notes.1.len()
+    );
+
+    Ok(moved_notes)
+}
+
+pub async fn delete_note(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    user_id: String,
+    note_id: String,
+) -> Result<usize, Error> {
+    let database_connection = establish_database_connection(database_connection_pool)?;
+
+    let delete_source = note::table.find((user_id, note_id));
+    let num_deleted = diesel::delete(delete_source).execute(&database_connection)?;
+
+    println!("Deleted {} notes", num_deleted);
+
+    if num_deleted == 0 {
+        Err(Error::NoteNotFound)
+    } else {
+        Ok(num_deleted)
+    }
+}
+
+pub async fn delete_all_notes(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    user_id: String,
+) -> Result<usize, Error> {
+    let database_connection = establish_database_connection(database_connection_pool)?;
+
+    let delete_source = note::table.filter(note::user_id.eq(user_id));
+    let num_deleted = diesel::delete(delete_source).execute(&database_connection)?;
+
+    println!("Deleted {} notes", num_deleted);
+
+    Ok(num_deleted)
+}
+
+// Violated constraint tells, whether note itself or one of its contents is duplicate
+fn map_unique_violation_error(error: DieselError) -> Error {
+    match &error {
+        DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, info) => {
+            match info.constraint_name() {
+                Some(NOTE_PRIMARY_KEY_CONSTRAINT) => Error::NoteAlreadyExists,
+                Some(NOTE_CONTENT_PRIMARY_KEY_CONSTRAINT) => Error::DuplicateNoteContentId,
+                _ => Error::from(error),
+            }
+        }
+        _ => Error::from(error),
+    }
+}
+
+fn map_not_found_error(error: DieselError) -> Error {
+    match error {
+        DieselError::NotFound => Error::NoteNotFound,
+        _ => Error::from(error),
+    }
+}
@@ -0,0 +1,35 @@
+use super::{
+    notes_data::NoteContentType,
+    schema::{note, note_content},
+};
+use diesel::{Insertable, Queryable};
+use std::{
+    fmt::{Display, Formatter},
+    time::SystemTime,
+};
+
+#[derive(Insertable, Queryable)]
+#[table_name = "note"]
+pub struct NoteEntity {
+    pub user_id: String,
+    pub note_id: String,
+    pub date_time_created: SystemTime,
+    pub date_time_last_edited: SystemTime,
+}
+
+impl Display for NoteEntity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.note_id)
+    }
+}
+
+#[derive(Insertable, Queryable)]
+#[table_name = "note_content"]
+pub struct NoteContentEntity {
+    pub user_id: String,
+    pub note_id: String,
+    pub note_content_id: String,
+    pub order_number: i32,
+    pub content_type: NoteContentType,
+    pub content: String,
+}
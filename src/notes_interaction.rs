@@ -0,0 +1,192 @@
+use super::{
+    error_data::Error,
+    notes_data::{Note, NoteContent, NoteContentType, PaginationInfo},
+    notes_database,
+    notes_entity::{NoteContentEntity, NoteEntity},
+    postgres_database_connection::PostgresDatabaseConnectionPool,
+    security_data::AuthenticatedUser,
+};
+use std::sync::Arc;
+
+// Keeps single request from loading all notes of user at once
+const MAX_PAGE_SIZE: i32 = 100;
+
+pub async fn get_notes(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    authenticated_user: AuthenticatedUser,
+    pagination_info: PaginationInfo,
+) -> Result<Vec<Note>, Error> {
+    let user_id = authenticated_user.user_id;
+
+    // Otherwise invalid values end up in LIMIT and OFFSET of query
+    if pagination_info.page < 0 || pagination_info.page_size <= 0 {
+        return Err(Error::InvalidPagination);
+    }
+    if pagination_info.page_size > MAX_PAGE_SIZE {
+        return Err(Error::PageSizeTooLarge(MAX_PAGE_SIZE));
+    }
+
+    let notes_entities = notes_database::get_notes(
+        database_connection_pool,
+        user_id,
+        pagination_info.page_size as i64,
+        pagination_info.page as i64,
+    )
+    .await?;
+
+    Ok(notes_entities
+        .into_iter()
+        .map(|(note_entity, note_content_entities)| {
+            map_note_entity_to_note(note_entity, note_content_entities)
+        })
+        .collect())
+}
+
+pub async fn get_note(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    authenticated_user: AuthenticatedUser,
+    note_id: String,
+) -> Result<Note, Error> {
+    let user_id = authenticated_user.user_id;
+
+    let (note_entity, note_content_entities) =
+        notes_database::get_note(database_connection_pool, user_id, note_id).await?;
+
+    Ok(map_note_entity_to_note(note_entity, note_content_entities))
+}
+
+pub async fn create_note(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    authenticated_user: AuthenticatedUser,
+    note: Note,
+) -> Result<Note, Error> {
+    let user_id = authenticated_user.user_id;
+
+    let (note_entity, note_content_entities) = map_note_to_note_entity(user_id, note);
+
+    let (note_entity, note_content_entities) =
+        notes_database::insert_note(database_connection_pool, note_entity, note_content_entities)
+            .await?;
+
+    Ok(map_note_entity_to_note(note_entity, note_content_entities))
+}
+
+pub async fn update_note(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    authenticated_user: AuthenticatedUser,
+    note_id: String,
+    note: Note,
+) -> Result<Note, Error> {
+    let user_id = authenticated_user.user_id;
+
+    let note = Note {
+        id: note_id,
+        ..note
+    };
+    let (note_entity, note_content_entities) = map_note_to_note_entity(user_id, note);
+
+    let (note_entity, note_content_entities) =
+        notes_database::update_note(database_connection_pool, note_entity, note_content_entities)
+            .await?;
+
+    Ok(map_note_entity_to_note(note_entity, note_content_entities))
+}
+
+pub async fn delete_note(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    authenticated_user: AuthenticatedUser,
+    note_id: String,
+) -> Result<usize, Error> {
+    let user_id = authenticated_user.user_id;
+
+    notes_database::delete_note(database_connection_pool, user_id, note_id).await
+}
+
+pub async fn delete_all_notes(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    authenticated_user: AuthenticatedUser,
+) -> Result<usize, Error> {
+    let user_id = authenticated_user.user_id;
+
+    notes_database::delete_all_notes(database_connection_pool, user_id).await
+}
+
+fn map_note_to_note_entity(user_id: String, note: Note) -> (NoteEntity, Vec<NoteContentEntity>) {
+    let note_content_entities = note
+        .note_content
+        .into_iter()
+        .enumerate()
+        .map(|(order_number, note_content)| {
+            map_note_content_to_note_content_entity(
+                user_id.clone(),
+                note.id.clone(),
+                order_number as i32,
+                note_content,
+            )
+        })
+        .collect();
+
+    let note_entity = NoteEntity {
+        user_id,
+        note_id: note.id,
+        date_time_created: note.date_time_created.into(),
+        date_time_last_edited: note.date_time_last_edited.into(),
+    };
+
+    (note_entity, note_content_entities)
+}
+
+fn map_note_content_to_note_content_entity(
+    user_id: String,
+    note_id: String,
+    order_number: i32,
+    note_content: NoteContent,
+) -> NoteContentEntity {
+    let (note_content_id, content_type, content) = match note_content {
+        NoteContent::Text { id, content } => (id, NoteContentType::Text, content),
+        NoteContent::Image { id, content_url } => (id, NoteContentType::Image, content_url),
+        NoteContent::Audio { id, content_url } => (id, NoteContentType::Audio, content_url),
+    };
+
+    NoteContentEntity {
+        user_id,
+        note_id,
+        note_content_id,
+        order_number,
+        content_type,
+        content,
+    }
+}
+
+fn map_note_entity_to_note(
+    note_entity: NoteEntity,
+    note_content_entities: Vec<NoteContentEntity>,
+) -> Note {
+    Note {
+        id: note_entity.note_id,
+        date_time_created: note_entity.date_time_created.into(),
+        date_time_last_edited: note_entity.date_time_last_edited.into(),
+        note_content: note_content_entities
+            .into_iter()
+            .map(map_note_content_entity_to_note_content)
+            .collect(),
+    }
+}
+
+fn map_note_content_entity_to_note_content(note_content_entity: NoteContentEntity) -> NoteContent {
+    let id = note_content_entity.note_content_id;
+    match note_content_entity.content_type {
+        NoteContentType::Text => NoteContent::Text {
+            id,
+            content: note_content_entity.content,
+        },
+        NoteContentType::Image => NoteContent::Image {
+            id,
+            content_url: note_content_entity.content,
+        },
+        NoteContentType::Audio => NoteContent::Audio {
+            id,
+            content_url: note_content_entity.content,
+        },
+    }
+}
@@ -1,3 +1,4 @@
+use super::error_data::Error;
 use diesel::{
     pg::PgConnection,
     r2d2::{ConnectionManager, Pool, PooledConnection},
@@ -20,6 +21,6 @@ pub fn get_database_connection_pool() -> PostgresDatabaseConnectionPool {
 
 pub fn establish_database_connection(
     database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
-) -> PostgresDatabasePooledConnection {
-    database_connection_pool.get().unwrap()
+) -> Result<PostgresDatabasePooledConnection, Error> {
+    Ok(database_connection_pool.get()?)
 }
@@ -0,0 +1,152 @@
+use super::utils::get_env_var_or_default;
+use dotenv::dotenv;
+use lazy_static::lazy_static;
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+// Limiter is cleaned from outdated windows once it grows over this size
+const RATE_LIMITER_CLEANUP_SIZE: usize = 10_000;
+
+lazy_static! {
+    pub static ref USER_NAME_AVAILABILITY_RATE_LIMITER: RateLimiter = RateLimiter::new(
+        "USER_NAME_AVAILABILITY_RATE_LIMIT",
+        10,
+        "USER_NAME_AVAILABILITY_RATE_LIMIT_WINDOW_SECONDS",
+        60,
+    );
+    // Limits guessing of second factor codes for every user
+    pub static ref SECOND_FACTOR_RATE_LIMITER: RateLimiter = RateLimiter::new(
+        "SECOND_FACTOR_RATE_LIMIT",
+        10,
+        "SECOND_FACTOR_RATE_LIMIT_WINDOW_SECONDS",
+        5 * 60,
+    );
+    pub static ref PASSWORD_RESET_RATE_LIMITER: RateLimiter = RateLimiter::new(
+        "PASSWORD_RESET_RATE_LIMIT",
+        5,
+        "PASSWORD_RESET_RATE_LIMIT_WINDOW_SECONDS",
+        60 * 60,
+    );
+    // Limits mails with email verification codes and guessing of password for every user
+    pub static ref EMAIL_CHANGE_RATE_LIMITER: RateLimiter = RateLimiter::new(
+        "EMAIL_CHANGE_RATE_LIMIT",
+        5,
+        "EMAIL_CHANGE_RATE_LIMIT_WINDOW_SECONDS",
+        60 * 60,
+    );
+    // Limits guessing of current password with stolen access token for every user
+    pub static ref CURRENT_PASSWORD_RATE_LIMITER: RateLimiter = RateLimiter::new(
+        "CURRENT_PASSWORD_RATE_LIMIT",
+        10,
+        "CURRENT_PASSWORD_RATE_LIMIT_WINDOW_SECONDS",
+        5 * 60,
+    );
+}
+
+// Allows every client at most `limit` requests per fixed window.
+// State is kept in memory, so every server instance limits its clients separately
+pub struct RateLimiter {
+    limit: u32,
+    window: Duration,
+    request_windows: Mutex<HashMap<String, RequestWindow>>,
+}
+
+struct RequestWindow {
+    started_at: Instant,
+    num_requests: u32,
+}
+
+impl RateLimiter {
+    fn new(limit_key: &str, default_limit: u32, window_key: &str, default_window: u64) -> Self {
+        dotenv().ok();
+        RateLimiter {
+            limit: get_env_var_or_default(limit_key, default_limit),
+            window: Duration::from_secs(get_env_var_or_default(window_key, default_window)),
+            request_windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Returns false, if client has run out of requests in current window
+    pub fn try_acquire(&self, client_key: &str) -> bool {
+        self.try_acquire_at(client_key, Instant::now())
+    }
+
+    fn try_acquire_at(&self, client_key: &str, now: Instant) -> bool {
+        let mut request_windows = self.request_windows.lock().unwrap();
+
+        if request_windows.len() >= RATE_LIMITER_CLEANUP_SIZE {
+            request_windows.retain(|_, request_window| {
+                now.duration_since(request_window.started_at) < self.window
+            });
+        }
+
+        let request_window = request_windows
+            .entry(client_key.to_owned())
+            .or_insert_with(|| RequestWindow {
+                started_at: now,
+                num_requests: 0,
+            });
+
+        if now.duration_since(request_window.started_at) >= self.window {
+            request_window.started_at = now;
+            request_window.num_requests = 0;
+        }
+
+        if request_window.num_requests < self.limit {
+            request_window.num_requests += 1;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: Duration = Duration::from_secs(60);
+
+    fn rate_limiter(limit: u32) -> RateLimiter {
+        RateLimiter {
+            limit,
+            window: WINDOW,
+            request_windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    #[test]
+    fn requests_over_limit_are_rejected() {
+        let rate_limiter = rate_limiter(3);
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            assert!(rate_limiter.try_acquire_at("client", now));
+        }
+        assert!(!rate_limiter.try_acquire_at("client", now + WINDOW / 2));
+    }
+
+    #[test]
+    fn limit_is_reset_once_window_ends() {
+        let rate_limiter = rate_limiter(1);
+        let now = Instant::now();
+
+        assert!(rate_limiter.try_acquire_at("client", now));
+        assert!(!rate_limiter.try_acquire_at("client", now + WINDOW - Duration::from_secs(1)));
+        assert!(rate_limiter.try_acquire_at("client", now + WINDOW));
+        assert!(!rate_limiter.try_acquire_at("client", now + WINDOW));
+    }
+
+    #[test]
+    fn clients_are_limited_separately() {
+        let rate_limiter = rate_limiter(1);
+        let now = Instant::now();
+
+        assert!(rate_limiter.try_acquire_at("client", now));
+        assert!(!rate_limiter.try_acquire_at("client", now));
+        assert!(rate_limiter.try_acquire_at("other_client", now));
+    }
+}
@@ -1,3 +1,63 @@
+table! {
+    account_token (token_hash) {
+        token_hash -> Text,
+        user_id -> Text,
+        purpose -> Text,
+        email -> Varchar,
+        created_at -> Timestamp,
+        expires_at -> Timestamp,
+    }
+}
+
+table! {
+    consumed_refresh_token (refresh_token_id) {
+        refresh_token_id -> Text,
+        session_id -> Text,
+        consumed_at -> Timestamp,
+    }
+}
+
+table! {
+    note (user_id, note_id) {
+        user_id -> Text,
+        note_id -> Text,
+        date_time_created -> Timestamp,
+        date_time_last_edited -> Timestamp,
+    }
+}
+
+table! {
+    note_content (user_id, note_id, note_content_id) {
+        user_id -> Text,
+        note_id -> Text,
+        note_content_id -> Text,
+        order_number -> Int4,
+        content_type -> Text,
+        content -> Text,
+    }
+}
+
+table! {
+    recovery_code (recovery_code_id) {
+        recovery_code_id -> Text,
+        user_id -> Text,
+        code_hash -> Text,
+    }
+}
+
+table! {
+    session (session_id) {
+        session_id -> Text,
+        user_id -> Text,
+        device_name -> Nullable<Text>,
+        user_agent -> Nullable<Text>,
+        created_at -> Timestamp,
+        last_used_at -> Timestamp,
+        refresh_token_hash -> Text,
+        refresh_token_id -> Text,
+    }
+}
+
 table! {
     user_account (user_id) {
         user_id -> Text,
@@ -6,7 +66,26 @@ table! {
         password_hash -> Nullable<Text>,
         password_hash_salt -> Nullable<Text>,
         password_hash_algorithm -> Nullable<Text>,
-        access_token -> Text,
-        refresh_token -> Text,
+        email -> Nullable<Varchar>,
+        is_email_verified -> Bool,
+        totp_secret -> Nullable<Text>,
+        is_totp_enabled -> Bool,
+        totp_last_used_step -> Nullable<Int8>,
     }
 }
+
+joinable!(account_token -> user_account (user_id));
+joinable!(consumed_refresh_token -> session (session_id));
+joinable!(note -> user_account (user_id));
+joinable!(recovery_code -> user_account (user_id));
+joinable!(session -> user_account (user_id));
+
+allow_tables_to_appear_in_same_query!(
+    account_token,
+    consumed_refresh_token,
+    note,
+    note_content,
+    recovery_code,
+    session,
+    user_account,
+);
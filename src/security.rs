@@ -1,188 +1,966 @@
 use super::{
     error_data::Error,
-    security_data::{AuthToken, Claims, HashAlgorithm, HashData, PasswordStrengthIssue},
-    utils::{DIGITS_REGEX, LOWER_CASE_LETTER_REGEX, SYMBOLS_REGEX, UPPER_CASE_LETTER_REGEX},
+    jwt_keys,
+    security_data::{
+        AccountTokenPurpose, AuthToken, Claims, HashAlgorithm, HashData, JwtClaimsPolicy,
+        PasswordDenylist, PasswordPolicy, PasswordStrengthIssue, TokenLifetimePolicy,
+        TokenLifetimes, TokenType, UserNameIssue,
+    },
+    utils::{
+        decode_hex, get_env_var_or_default, DIGITS_REGEX, EMAIL_REGEX, LOWER_CASE_LETTER_REGEX,
+        SYMBOLS_REGEX, UPPER_CASE_LETTER_REGEX, USER_NAME_REGEX,
+    },
 };
-use actix_web::http::HeaderMap;
 use argon2::{
-    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    password_hash::{
+        rand_core::{OsRng, RngCore},
+        PasswordHash, PasswordHasher, PasswordVerifier, SaltString,
+    },
     Argon2,
 };
-use chrono::{Duration, Utc};
+use chrono::{DateTime, Duration, Utc};
+use data_encoding::BASE32_NOPAD;
 use dotenv::dotenv;
-use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use hmac::{Hmac, Mac};
+use jsonwebtoken::{decode, decode_header, encode, errors::ErrorKind, Header, Validation};
 use lazy_static::lazy_static;
-use std::env;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashSet,
+    env,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::PathBuf,
+    time::SystemTime,
+};
+use subtle::ConstantTimeEq;
+use unicode_normalization::UnicodeNormalization;
+use uuid::Uuid;
+
+const PLAIN_TEXT_DENYLIST_FORMAT: &str = "plain";
+const SHA1_DENYLIST_FORMAT: &str = "sha1";
+
+// Limit of email columns, which is maximum length of email address path
+const EMAIL_MAX_LENGTH: usize = 254;
+const ACCOUNT_TOKEN_LENGTH: usize = 32;
 
-const AUTHORIZATION_HEADER_KEY: &str = "Authorization";
-const BEARER_PREFIX: &str = "Bearer ";
+// RFC 6238 defaults, that authenticator apps support
+const TOTP_ISSUER: &str = "MachineNotes";
+const TOTP_SECRET_LENGTH: usize = 20;
+const TOTP_PERIOD_SECONDS: i64 = 30;
+const TOTP_DIGITS: u32 = 6;
+// Codes of neighbouring time steps are accepted too, because of clock skew
+const TOTP_ALLOWED_STEP_DRIFT: i64 = 1;
+const RECOVERY_CODE_LENGTH: usize = 10;
+
+const USER_NAME_MIN_LENGTH: usize = 3;
+const USER_NAME_MAX_LENGTH: usize = 32;
+const RESERVED_USER_NAMES: [&str; 10] = [
+    "admin",
+    "administrator",
+    "root",
+    "system",
+    "support",
+    "moderator",
+    "guest",
+    "null",
+    "undefined",
+    "machine_notes",
+];
 
 lazy_static! {
-    static ref JWT_SECRET: String = get_jwt_secret();
+    pub static ref REFRESH_TOKEN_HASH_SECRET: String = get_refresh_token_hash_secret();
+    pub static ref PASSWORD_POLICY: PasswordPolicy = get_password_policy();
+    static ref JWT_CLAIMS_POLICY: JwtClaimsPolicy = get_jwt_claims_policy();
+    static ref TOKEN_LIFETIME_POLICY: TokenLifetimePolicy = get_token_lifetime_policy();
+    static ref EMAIL_VERIFICATION_TOKEN_LIFETIME: Duration = Duration::seconds(
+        get_env_var_or_default("EMAIL_VERIFICATION_TOKEN_LIFETIME_SECONDS", 24 * 60 * 60)
+    );
+    static ref PASSWORD_RESET_TOKEN_LIFETIME: Duration = Duration::seconds(get_env_var_or_default(
+        "PASSWORD_RESET_TOKEN_LIFETIME_SECONDS",
+        60 * 60
+    ));
+    static ref MFA_CHALLENGE_TOKEN_LIFETIME: Duration = Duration::seconds(get_env_var_or_default(
+        "MFA_CHALLENGE_TOKEN_LIFETIME_SECONDS",
+        5 * 60
+    ));
 }
 
-// Requirements:
+// Requirements are set by PasswordPolicy, by default password must:
 // 1. be 8 to 16 symbols long
-// 2. contain at least one uppercase letter
-// 3. contain at least one lowercase letter
-// 4. contain at least one digit
-// 5. contain at least one /p{Punct} symbol
-// 6. have at least one unique symbol for each four
+// 2. contain no whitespace
+// 3. contain at least one uppercase letter
+// 4. contain at least one lowercase letter
+// 5. contain at least one digit
+// 6. contain at least one /p{Punct} symbol
+// 7. have at least one unique symbol for each four
+// 8. not be in offline denylist of breached or common passwords, if one is configured
 //
-// We don't currently check, that password is in worst passwords list,
-// because at 2022 there is no password in such lists, that meet such requirements
-pub fn check_password_strength(password: &str) -> PasswordStrengthIssue {
-    // tbd should this be here or on client side?
-    // if password.len() < 8 {
-    //     PasswordStrengthIssue::TooShort
-    // } else if password.len() > 16 {
-    //     PasswordStrengthIssue::TooLong
-    // } else if password.contains(char::is_whitespace)
-    // || password.as_bytes().iter().any(u8::is_ascii_whitespace) {
-    // PasswordStrengthIssue::ContainsWhitespace }
-    // else if !UPPER_CASE_LETTER_REGEX.is_match(password) {
-    //     PasswordStrengthIssue::ContainsNoUpperCaseLetter
-    // } else if !LOWER_CASE_LETTER_REGEX.is_match(password) {
-    //     PasswordStrengthIssue::ContainsNoLowerCaseLetter
-    // } else if !DIGITS_REGEX.is_match(password) {
-    //     PasswordStrengthIssue::ContainsNoDigit
-    // } else if !SYMBOLS_REGEX.is_match(password) {
-    //     PasswordStrengthIssue::ContainsNoSymbol
-    // } else {
-    //     PasswordStrengthIssue::None
-    // }
-    PasswordStrengthIssue::None
-}
-
-pub fn generate_password_hash(password: &[u8]) -> HashData {
+// Returns every violated requirement, so that client could show all of them at once.
+pub fn check_password_strength(
+    password: &str,
+    password_policy: &PasswordPolicy,
+) -> Vec<PasswordStrengthIssue> {
+    let mut password_strength_issues = Vec::new();
+
+    let password_length = password.chars().count();
+
+    if password_length < password_policy.min_length {
+        password_strength_issues.push(PasswordStrengthIssue::TooShort);
+    }
+    if password_length > password_policy.max_length {
+        password_strength_issues.push(PasswordStrengthIssue::TooLong);
+    }
+    if password.contains(char::is_whitespace) {
+        password_strength_issues.push(PasswordStrengthIssue::ContainsWhitespace);
+    }
+    if password_policy.require_upper_case_letter && !UPPER_CASE_LETTER_REGEX.is_match(password) {
+        password_strength_issues.push(PasswordStrengthIssue::ContainsNoUpperCaseLetter);
+    }
+    if password_policy.require_lower_case_letter && !LOWER_CASE_LETTER_REGEX.is_match(password) {
+        password_strength_issues.push(PasswordStrengthIssue::ContainsNoLowerCaseLetter);
+    }
+    if password_policy.require_digit && !DIGITS_REGEX.is_match(password) {
+        password_strength_issues.push(PasswordStrengthIssue::ContainsNoDigit);
+    }
+    if password_policy.require_symbol && !SYMBOLS_REGEX.is_match(password) {
+        password_strength_issues.push(PasswordStrengthIssue::ContainsNoSymbol);
+    }
+    // Zero disables uniqueness requirement
+    if password_policy.symbols_per_unique_symbol > 0
+        && count_unique_symbols(password) * password_policy.symbols_per_unique_symbol
+            < password_length
+    {
+        password_strength_issues.push(PasswordStrengthIssue::SymbolUniquenessRequirementsViolation);
+    }
+    if let Some(denylist) = password_policy.denylist.as_ref() {
+        if denylist.contains(password) {
+            password_strength_issues.push(PasswordStrengthIssue::Compromised);
+        }
+    }
+
+    password_strength_issues
+}
+
+// User name is stored in NFKC form, so that names looking the same can't be registered twice
+pub fn normalize_user_name(user_name: &str) -> String {
+    user_name.nfkc().collect()
+}
+
+// Returns normalized user name, if it
+// 1. is from 3 to 32 characters long, the latter is limit of user_name column
+// 2. consists of letters, digits, '_', '.' and '-' only
+// 3. isn't reserved, regardless of case
+pub fn validate_user_name(user_name: &str) -> Result<String, Error> {
+    let user_name = normalize_user_name(user_name);
+    let mut user_name_issues = Vec::new();
+
+    let user_name_length = user_name.chars().count();
+
+    if user_name_length < USER_NAME_MIN_LENGTH {
+        user_name_issues.push(UserNameIssue::TooShort);
+    }
+    if user_name_length > USER_NAME_MAX_LENGTH {
+        user_name_issues.push(UserNameIssue::TooLong);
+    }
+    if !USER_NAME_REGEX.is_match(&user_name) {
+        user_name_issues.push(UserNameIssue::ContainsForbiddenCharacters);
+    }
+    if RESERVED_USER_NAMES.contains(&user_name.to_lowercase().as_str()) {
+        user_name_issues.push(UserNameIssue::Reserved);
+    }
+
+    if user_name_issues.is_empty() {
+        Ok(user_name)
+    } else {
+        Err(Error::InvalidUserName(user_name_issues))
+    }
+}
+
+// Only rough shape of address is checked, it's verified by mail anyway
+pub fn validate_email(email: &str) -> Result<String, Error> {
+    let email = email.trim();
+
+    if email.len() <= EMAIL_MAX_LENGTH && EMAIL_REGEX.is_match(email) {
+        Ok(email.to_owned())
+    } else {
+        Err(Error::InvalidEmail)
+    }
+}
+
+// Tokens, that are sent by mail, are random, so they can't be guessed
+pub fn generate_account_token() -> String {
+    let mut account_token = [0u8; ACCOUNT_TOKEN_LENGTH];
+    OsRng.fill_bytes(&mut account_token);
+    base64::encode_config(account_token, base64::URL_SAFE_NO_PAD)
+}
+
+// Account tokens have enough entropy to be stored as plain SHA-256 hashes
+pub fn hash_account_token(account_token: &str) -> String {
+    format!("{:x}", Sha256::digest(account_token.as_bytes()))
+}
+
+pub fn get_account_token_lifetime(account_token_purpose: &AccountTokenPurpose) -> Duration {
+    dotenv().ok();
+    match account_token_purpose {
+        AccountTokenPurpose::EmailVerification => *EMAIL_VERIFICATION_TOKEN_LIFETIME,
+        AccountTokenPurpose::PasswordReset => *PASSWORD_RESET_TOKEN_LIFETIME,
+    }
+}
+
+pub fn generate_totp_secret() -> String {
+    let mut totp_secret = [0u8; TOTP_SECRET_LENGTH];
+    OsRng.fill_bytes(&mut totp_secret);
+    BASE32_NOPAD.encode(&totp_secret)
+}
+
+// URI is shown as QR code, so that authenticator app can be set up by scanning it
+pub fn get_totp_uri(totp_secret: &str, user_name: &str) -> String {
+    format!(
+        "otpauth://totp/{issuer}:{user_name}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={digits}&period={period}",
+        issuer = TOTP_ISSUER,
+        user_name = utf8_percent_encode(user_name, NON_ALPHANUMERIC),
+        secret = totp_secret,
+        digits = TOTP_DIGITS,
+        period = TOTP_PERIOD_SECONDS,
+    )
+}
+
+// Recovery codes contain letters and are longer, so they never look like TOTP codes
+pub fn is_totp_code(code: &str) -> bool {
+    code.len() == TOTP_DIGITS as usize && code.chars().all(|character| character.is_ascii_digit())
+}
+
+// Returns time step, that code belongs to, so that it could be rejected when it's used again
+pub fn verify_totp_code(totp_secret: &str, totp_code: &str, timestamp: i64) -> Option<i64> {
+    let totp_secret = BASE32_NOPAD.decode(totp_secret.as_bytes()).ok()?;
+    let current_step = timestamp / TOTP_PERIOD_SECONDS;
+
+    (current_step - TOTP_ALLOWED_STEP_DRIFT..=current_step + TOTP_ALLOWED_STEP_DRIFT).find(|step| {
+        generate_totp_code(&totp_secret, *step)
+            .as_bytes()
+            .ct_eq(totp_code.as_bytes())
+            .into()
+    })
+}
+
+// Recovery codes are shown to user only once, they are stored as Argon2 hashes just like passwords
+pub fn generate_recovery_code() -> String {
+    let mut recovery_code = [0u8; RECOVERY_CODE_LENGTH];
+    OsRng.fill_bytes(&mut recovery_code);
+    let recovery_code = BASE32_NOPAD.encode(&recovery_code).to_lowercase();
+    let (first_half, second_half) = recovery_code.split_at(recovery_code.len() / 2);
+    format!("{}-{}", first_half, second_half)
+}
+
+// Users might type recovery code without dash or in other case
+pub fn normalize_recovery_code(recovery_code: &str) -> String {
+    recovery_code
+        .chars()
+        .filter(|character| character.is_ascii_alphanumeric())
+        .map(|character| character.to_ascii_lowercase())
+        .collect()
+}
+
+pub fn generate_password_hash(password: &[u8]) -> Result<HashData, Error> {
     let salt = SaltString::generate(&mut OsRng);
 
     let argon2 = get_argon_instance();
 
     // Hash password to PHC string ($argon2id$v=19$...)
-    let password_hash = argon2.hash_password(password, &salt).unwrap().to_string();
+    let password_hash = argon2
+        .hash_password(password, &salt)
+        .map_err(|_| Error::PasswordHashing)?
+        .to_string();
 
-    HashData {
+    Ok(HashData {
         salt: salt.as_str().to_string(),
         hash: password_hash,
         algorithm: HashAlgorithm::Argon2idV19,
-    }
+    })
 }
 
-pub fn verify_password(password: &[u8], password_hash: &str) -> bool {
+// Hash, that can't be parsed, is an error rather than a mismatch
+pub fn verify_password(password: &[u8], password_hash: &str) -> Result<bool, Error> {
     // Verify password against PHC string.
     //
     // NOTE: hash params from `parsed_hash` are used instead of what is configured in the
     // `Argon2` instance.
-    let parsed_hash = PasswordHash::new(password_hash).unwrap();
+    let parsed_hash = PasswordHash::new(password_hash).map_err(|_| Error::PasswordHashing)?;
 
-    Argon2::default()
+    Ok(Argon2::default()
         .verify_password(password, &parsed_hash)
-        .is_ok()
+        .is_ok())
 }
 
-pub fn generate_auth_token(user_id: String) -> AuthToken {
-    let access_token = generate_access_token(user_id.clone());
-    let refresh_token = generate_refresh_token(user_id);
-    AuthToken {
-        access_token: access_token,
-        refresh_token: refresh_token,
-    }
+// Session is expired, if it was created before the first returned time or
+// last refreshed before the second one, because none of its tokens can be valid then
+pub fn get_session_expiry_bounds(is_guest: bool) -> (SystemTime, SystemTime) {
+    let token_lifetimes = get_token_lifetimes(is_guest);
+    let now = Utc::now();
+    (
+        (now - token_lifetimes.max_session_age).into(),
+        (now - token_lifetimes.refresh_token_lifetime).into(),
+    )
+}
+
+// Refresh token is rotated along with access token, so every refresh token can be used only once.
+// Neither of tokens outlives session's max age.
+pub fn generate_auth_token(
+    user_id: String,
+    session_id: String,
+    is_guest: bool,
+    session_created_at: DateTime<Utc>,
+) -> Result<AuthToken, Error> {
+    let token_lifetimes = get_token_lifetimes(is_guest);
+    let now = Utc::now();
+    let session_expires_at = session_created_at + token_lifetimes.max_session_age;
+
+    let access_token_expires_at =
+        (now + token_lifetimes.access_token_lifetime).min(session_expires_at);
+    let access_token = generate_jwt(
+        user_id.clone(),
+        session_id.clone(),
+        Uuid::new_v4().to_string(),
+        TokenType::Access,
+        access_token_expires_at,
+    )?;
+
+    let refresh_token_id = Uuid::new_v4().to_string();
+    let refresh_token_expires_at =
+        (now + token_lifetimes.refresh_token_lifetime).min(session_expires_at);
+    let refresh_token = generate_jwt(
+        user_id,
+        session_id,
+        refresh_token_id.clone(),
+        TokenType::Refresh,
+        refresh_token_expires_at,
+    )?;
+
+    Ok(AuthToken {
+        access_token,
+        refresh_token,
+        refresh_token_id,
+    })
 }
 
-pub fn get_access_token_from_request_headers(headers: &HeaderMap) -> String {
-    let untrimmed_access_token = get_untrimmed_access_token_from_request_headers(headers);
-    trim_access_token(untrimmed_access_token).to_owned()
+// Challenge isn't bound to any session yet, session is started once second factor is checked
+pub fn generate_mfa_challenge_token(user_id: String) -> Result<String, Error> {
+    dotenv().ok();
+    generate_jwt(
+        user_id,
+        String::new(),
+        Uuid::new_v4().to_string(),
+        TokenType::MfaChallenge,
+        Utc::now() + *MFA_CHALLENGE_TOKEN_LIFETIME,
+    )
 }
 
-pub fn verify_access_token_from_request_headers(headers: &HeaderMap) -> bool {
-    let untrimmed_access_token = get_untrimmed_access_token_from_request_headers(headers);
-    if untrimmed_access_token.starts_with(BEARER_PREFIX) {
-        let trimmed_access_token = trim_access_token(untrimmed_access_token);
-        // tbd get real decoding errors
-        verify_jwt(trimmed_access_token)
+// Token is verified with key, that its kid header points to,
+// and is accepted only for use, that its typ claim is issued for.
+// Expired token is told apart, so client knows it has to get a new one
+pub fn decode_jwt(jwt: &str, token_type: TokenType) -> Result<Claims, Error> {
+    let header = decode_header(jwt).map_err(|_| Error::JWTTokenDecoding)?;
+    let jwt_key =
+        jwt_keys::get_verification_key(header.kid.as_deref()).ok_or(Error::JWTTokenDecoding)?;
+
+    let mut validation = Validation::new(jwt_key.algorithm);
+    validation.set_issuer(&[&JWT_CLAIMS_POLICY.issuer]);
+    validation.set_audience(&[&JWT_CLAIMS_POLICY.audience]);
+    validation.set_required_spec_claims(&["exp", "nbf", "iss", "aud", "sub"]);
+    validation.validate_nbf = true;
+    validation.leeway = JWT_CLAIMS_POLICY.leeway_seconds;
+
+    let claims = decode::<Claims>(jwt, &jwt_key.decoding_key, &validation)
+        .map(|token_data| token_data.claims)
+        .map_err(|error| match error.kind() {
+            ErrorKind::ExpiredSignature => Error::JWTTokenExpired,
+            _ => Error::JWTTokenDecoding,
+        })?;
+
+    if claims.typ == token_type {
+        Ok(claims)
     } else {
-        false
+        Err(Error::JWTTokenDecoding)
     }
 }
 
-pub fn verify_jwt(jwt: &str) -> bool {
-    decode::<Claims>(jwt, &get_decoding_key(), &get_jwt_validation_algorithm())
-        .map_err(|_| Error::JWTTokenDecodingError)
-        .is_ok()
+fn count_unique_symbols(password: &str) -> usize {
+    password.chars().collect::<HashSet<char>>().len()
 }
 
-pub fn refresh_access_token(refresh_token: String, user_id: String) -> String {
-    if verify_jwt(refresh_token.as_str()) {
-        generate_access_token(user_id)
-    } else {
-        panic!("Refresh token was not verified")
+// Refresh tokens are stored only as keyed hashes, so that database dump can't be used to sign in
+pub fn hash_refresh_token(refresh_token: &str) -> String {
+    let mut mac = get_refresh_token_mac();
+    mac.update(refresh_token.as_bytes());
+    format!("{:x}", mac.finalize().into_bytes())
+}
+
+pub fn verify_refresh_token_hash(refresh_token: &str, refresh_token_hash: &str) -> bool {
+    match decode_hex(refresh_token_hash) {
+        Some(refresh_token_hash) => {
+            let mut mac = get_refresh_token_mac();
+            mac.update(refresh_token.as_bytes());
+            // Constant time comparison
+            mac.verify_slice(&refresh_token_hash).is_ok()
+        }
+        None => false,
     }
 }
 
+// HOTP value of RFC 4226 for given time step
+fn generate_totp_code(totp_secret: &[u8], step: i64) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(totp_secret).expect("HMAC can take key of any size");
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary_code = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+
+    format!(
+        "{:0width$}",
+        binary_code % 10u32.pow(TOTP_DIGITS),
+        width = TOTP_DIGITS as usize
+    )
+}
+
+fn get_refresh_token_mac() -> Hmac<Sha256> {
+    Hmac::<Sha256>::new_from_slice(REFRESH_TOKEN_HASH_SECRET.as_bytes())
+        .expect("HMAC can take key of any size")
+}
+
 // Argon2 with default params (Argon2id v19)
 fn get_argon_instance<'a>() -> Argon2<'a> {
     Argon2::default()
 }
 
-fn get_jwt_secret() -> String {
+fn get_refresh_token_hash_secret() -> String {
     dotenv().ok();
-    env::var("JWT_SECRET").expect("DATABASE_URL must be set")
+    env::var("REFRESH_TOKEN_HASH_SECRET").expect("REFRESH_TOKEN_HASH_SECRET must be set")
 }
 
-fn get_untrimmed_access_token_from_request_headers<'a>(headers: &'a HeaderMap) -> &'a str {
-    headers
-        .get(AUTHORIZATION_HEADER_KEY)
-        .unwrap()
-        .to_str()
-        .unwrap()
+fn get_token_lifetimes(is_guest: bool) -> &'static TokenLifetimes {
+    if is_guest {
+        &TOKEN_LIFETIME_POLICY.guest
+    } else {
+        &TOKEN_LIFETIME_POLICY.registered_user
+    }
 }
 
-fn trim_access_token(access_token: &str) -> &str {
-    access_token.trim_start_matches(BEARER_PREFIX)
+// Guests get lifetimes of registered users, unless GUEST_ variables are set
+fn get_token_lifetime_policy() -> TokenLifetimePolicy {
+    dotenv().ok();
+    let registered_user = get_token_lifetimes_from_env("", TokenLifetimes::default());
+    let guest = get_token_lifetimes_from_env("GUEST_", registered_user);
+    TokenLifetimePolicy {
+        registered_user,
+        guest,
+    }
 }
 
-fn generate_access_token(user_id: String) -> String {
-    // tbd
-    generate_jwt(user_id, Duration::days(1))
+fn get_token_lifetimes_from_env(
+    prefix: &str,
+    default_token_lifetimes: TokenLifetimes,
+) -> TokenLifetimes {
+    let get_duration = |key: &str, default: Duration| {
+        Duration::seconds(get_env_var_or_default(
+            &format!("{}{}", prefix, key),
+            default.num_seconds(),
+        ))
+    };
+    TokenLifetimes {
+        access_token_lifetime: get_duration(
+            "ACCESS_TOKEN_LIFETIME_SECONDS",
+            default_token_lifetimes.access_token_lifetime,
+        ),
+        refresh_token_lifetime: get_duration(
+            "REFRESH_TOKEN_LIFETIME_SECONDS",
+            default_token_lifetimes.refresh_token_lifetime,
+        ),
+        max_session_age: get_duration(
+            "MAX_SESSION_AGE_SECONDS",
+            default_token_lifetimes.max_session_age,
+        ),
+    }
 }
 
-fn generate_refresh_token(user_id: String) -> String {
-    generate_jwt(user_id, Duration::days(30))
+fn get_jwt_claims_policy() -> JwtClaimsPolicy {
+    dotenv().ok();
+    let default_jwt_claims_policy = JwtClaimsPolicy::default();
+    JwtClaimsPolicy {
+        issuer: get_env_var_or_default("JWT_ISSUER", default_jwt_claims_policy.issuer),
+        audience: get_env_var_or_default("JWT_AUDIENCE", default_jwt_claims_policy.audience),
+        leeway_seconds: get_env_var_or_default(
+            "JWT_LEEWAY_SECONDS",
+            default_jwt_claims_policy.leeway_seconds,
+        ),
+    }
 }
 
-fn generate_jwt(user_id: String, valid_for: Duration) -> String {
-    // tbd maybe use key.pem with EncodingKey::from_rsa_pem(include_bytes!("privkey.pem"))?
-    let encoding_key = EncodingKey::from_secret(JWT_SECRET.as_bytes());
+fn get_password_policy() -> PasswordPolicy {
+    dotenv().ok();
+    let default_password_policy = PasswordPolicy::default();
+    PasswordPolicy {
+        min_length: get_env_var_or_default(
+            "PASSWORD_MIN_LENGTH",
+            default_password_policy.min_length,
+        ),
+        max_length: get_env_var_or_default(
+            "PASSWORD_MAX_LENGTH",
+            default_password_policy.max_length,
+        ),
+        require_upper_case_letter: get_env_var_or_default(
+            "PASSWORD_REQUIRE_UPPER_CASE_LETTER",
+            default_password_policy.require_upper_case_letter,
+        ),
+        require_lower_case_letter: get_env_var_or_default(
+            "PASSWORD_REQUIRE_LOWER_CASE_LETTER",
+            default_password_policy.require_lower_case_letter,
+        ),
+        require_digit: get_env_var_or_default(
+            "PASSWORD_REQUIRE_DIGIT",
+            default_password_policy.require_digit,
+        ),
+        require_symbol: get_env_var_or_default(
+            "PASSWORD_REQUIRE_SYMBOL",
+            default_password_policy.require_symbol,
+        ),
+        symbols_per_unique_symbol: get_env_var_or_default(
+            "PASSWORD_SYMBOLS_PER_UNIQUE_SYMBOL",
+            default_password_policy.symbols_per_unique_symbol,
+        ),
+        denylist: env::var("PASSWORD_DENYLIST_FILE")
+            .ok()
+            .map(|denylist_file_path| {
+                let denylist_format = env::var("PASSWORD_DENYLIST_FORMAT")
+                    .unwrap_or_else(|_| PLAIN_TEXT_DENYLIST_FORMAT.to_owned());
+                load_password_denylist(&denylist_file_path, &denylist_format)
+            }),
+    }
+}
 
-    let now = Utc::now();
-    let iat = now.timestamp();
-    let exp = now
-        .checked_add_signed(valid_for)
-        .expect("valid timestamp")
-        .timestamp();
+// Plain text denylist is loaded into memory, while SHA-1 one is only read range by range
+fn load_password_denylist(denylist_file_path: &str, denylist_format: &str) -> PasswordDenylist {
+    let password_denylist = match denylist_format {
+        PLAIN_TEXT_DENYLIST_FORMAT => {
+            let denylist_file = File::open(denylist_file_path)
+                .unwrap_or_else(|error| panic!("Can't open {}: {}", denylist_file_path, error));
+            PasswordDenylist::PlainText(
+                BufReader::new(denylist_file)
+                    .lines()
+                    .map(|line| line.expect("Error reading password denylist"))
+                    .map(|line| line.trim().to_lowercase())
+                    .filter(|line| !line.is_empty())
+                    .collect(),
+            )
+        }
+        SHA1_DENYLIST_FORMAT => {
+            let range_directory_path = PathBuf::from(denylist_file_path);
+            if !range_directory_path.is_dir() {
+                panic!(
+                    "{} must be directory of SHA-1 range files",
+                    denylist_file_path
+                );
+            }
+            PasswordDenylist::Sha1Range(range_directory_path)
+        }
+        _ => panic!("Unknown password denylist format {}", denylist_format),
+    };
+
+    println!("Loaded password denylist from {}", denylist_file_path);
+
+    password_denylist
+}
+
+fn generate_jwt(
+    user_id: String,
+    session_id: String,
+    jti: String,
+    token_type: TokenType,
+    expires_at: DateTime<Utc>,
+) -> Result<String, Error> {
+    let jwt_key = jwt_keys::get_signing_key();
+
+    let iat = Utc::now().timestamp();
+    let exp = expires_at.timestamp();
 
     let claims = Claims {
+        iss: JWT_CLAIMS_POLICY.issuer.clone(),
+        aud: JWT_CLAIMS_POLICY.audience.clone(),
         sub: user_id,
-        iat: iat as usize,
+        sid: session_id,
         exp: exp as usize,
+        nbf: iat as usize,
+        iat: iat as usize,
+        jti,
+        typ: token_type,
     };
 
-    let header = Header::new(Algorithm::HS512);
+    let mut header = Header::new(jwt_key.algorithm);
+    header.kid = Some(jwt_key.key_id.clone());
 
-    encode(&header, &claims, &encoding_key)
-        .map_err(|_| Error::JWTTokenCreationError)
-        .unwrap()
-}
+    let encoding_key = jwt_key
+        .encoding_key
+        .as_ref()
+        .expect("Active key has private key");
 
-fn get_decoding_key() -> DecodingKey {
-    DecodingKey::from_secret(JWT_SECRET.as_bytes())
+    encode(&header, &claims, encoding_key).map_err(|_| Error::JWTTokenCreation)
 }
 
-fn get_jwt_validation_algorithm() -> Validation {
-    Validation::new(get_jwt_algorithm())
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    const VALID_PASSWORD: &str = "Secr3t!pass";
+
+    fn check(password: &str) -> Vec<PasswordStrengthIssue> {
+        check_password_strength(password, &PasswordPolicy::default())
+    }
+
+    #[test]
+    fn valid_password_has_no_issues() {
+        assert!(check(VALID_PASSWORD).is_empty());
+    }
+
+    #[test]
+    fn too_short_password() {
+        assert_eq!(check("Se3t!pa"), vec![PasswordStrengthIssue::TooShort]);
+    }
+
+    #[test]
+    fn too_long_password() {
+        assert_eq!(
+            check("Secr3t!passw0rd_is_long"),
+            vec![PasswordStrengthIssue::TooLong]
+        );
+    }
+
+    #[test]
+    fn password_length_is_counted_in_symbols() {
+        assert!(check("Secr3t!pässwörd").is_empty());
+    }
+
+    #[test]
+    fn password_with_whitespace() {
+        assert_eq!(
+            check("Secr3t pass!"),
+            vec![PasswordStrengthIssue::ContainsWhitespace]
+        );
+    }
+
+    #[test]
+    fn password_without_upper_case_letter() {
+        assert_eq!(
+            check("secr3t!pass"),
+            vec![PasswordStrengthIssue::ContainsNoUpperCaseLetter]
+        );
+    }
+
+    #[test]
+    fn password_without_lower_case_letter() {
+        assert_eq!(
+            check("SECR3T!PASS"),
+            vec![PasswordStrengthIssue::ContainsNoLowerCaseLetter]
+        );
+    }
+
+    #[test]
+    fn password_without_digit() {
+        assert_eq!(
+            check("Secret!pass"),
+            vec![PasswordStrengthIssue::ContainsNoDigit]
+        );
+    }
+
+    #[test]
+    fn password_without_symbol() {
+        assert_eq!(
+            check("Secr3tpass"),
+            vec![PasswordStrengthIssue::ContainsNoSymbol]
+        );
+    }
+
+    #[test]
+    fn password_with_too_few_unique_symbols() {
+        let password_policy = PasswordPolicy {
+            max_length: 32,
+            ..PasswordPolicy::default()
+        };
+
+        assert_eq!(
+            check_password_strength("Aa1!Aa1!Aa1!Aa1!A", &password_policy),
+            vec![PasswordStrengthIssue::SymbolUniquenessRequirementsViolation]
+        );
+        assert!(check_password_strength("Aa1!Aa1!Aa1!Aa1!b", &password_policy).is_empty());
+    }
+
+    #[test]
+    fn every_violated_requirement_is_returned() {
+        assert_eq!(
+            check("aaaa aaaa"),
+            vec![
+                PasswordStrengthIssue::ContainsWhitespace,
+                PasswordStrengthIssue::ContainsNoUpperCaseLetter,
+                PasswordStrengthIssue::ContainsNoDigit,
+                PasswordStrengthIssue::ContainsNoSymbol,
+                PasswordStrengthIssue::SymbolUniquenessRequirementsViolation,
+            ]
+        );
+    }
+
+    #[test]
+    fn custom_length_limits_are_applied() {
+        let password_policy = PasswordPolicy {
+            min_length: 12,
+            max_length: 64,
+            ..PasswordPolicy::default()
+        };
+
+        assert_eq!(
+            check_password_strength(VALID_PASSWORD, &password_policy),
+            vec![PasswordStrengthIssue::TooShort]
+        );
+        assert!(check_password_strength("Secr3t!passw0rd_is_long", &password_policy).is_empty());
+    }
+
+    #[test]
+    fn disabled_symbol_class_requirements_are_skipped() {
+        let password_policy = PasswordPolicy {
+            require_upper_case_letter: false,
+            require_lower_case_letter: false,
+            require_digit: false,
+            require_symbol: false,
+            ..PasswordPolicy::default()
+        };
+
+        assert!(check_password_strength("secretpass", &password_policy).is_empty());
+    }
+
+    #[test]
+    fn disabled_uniqueness_requirement_is_skipped() {
+        let password_policy = PasswordPolicy {
+            max_length: 32,
+            symbols_per_unique_symbol: 0,
+            ..PasswordPolicy::default()
+        };
+
+        assert!(check_password_strength("Aa1!Aa1!Aa1!Aa1!A", &password_policy).is_empty());
+    }
+
+    #[test]
+    fn password_from_plain_text_denylist_is_compromised() {
+        let password_policy = PasswordPolicy {
+            denylist: Some(PasswordDenylist::PlainText(
+                vec!["secr3t!pass".to_owned()].into_iter().collect(),
+            )),
+            ..PasswordPolicy::default()
+        };
 
-fn get_jwt_algorithm() -> Algorithm {
-    Algorithm::HS512
+        assert_eq!(
+            check_password_strength(VALID_PASSWORD, &password_policy),
+            vec![PasswordStrengthIssue::Compromised]
+        );
+        assert!(check_password_strength("Other3!pass", &password_policy).is_empty());
+    }
+
+    #[test]
+    fn password_from_sha1_range_denylist_is_compromised() {
+        // SHA-1 of "Secr3t!pass" is BA725BD00857EAB096691DE8F3BC049F20441E86
+        let range_directory = tempfile::tempdir().unwrap();
+        fs::write(
+            range_directory.path().join("BA725.txt"),
+            "0018A45C4D1DEF81644B54AB7F969B88D65:3\r\nbd00857eab096691de8f3bc049f20441e86:42\r\n",
+        )
+        .unwrap();
+
+        let password_policy = PasswordPolicy {
+            denylist: Some(load_password_denylist(
+                range_directory.path().to_str().unwrap(),
+                SHA1_DENYLIST_FORMAT,
+            )),
+            ..PasswordPolicy::default()
+        };
+
+        assert_eq!(
+            check_password_strength(VALID_PASSWORD, &password_policy),
+            vec![PasswordStrengthIssue::Compromised]
+        );
+        // Range of this password's prefix doesn't exist
+        assert!(check_password_strength("Other3!pass", &password_policy).is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn sha1_denylist_has_to_be_directory() {
+        let denylist_file = tempfile::NamedTempFile::new().unwrap();
+        load_password_denylist(denylist_file.path().to_str().unwrap(), SHA1_DENYLIST_FORMAT);
+    }
+
+    #[test]
+    fn valid_user_name_is_normalized() {
+        assert_eq!(validate_user_name("ｂｏｂ_1").unwrap(), "bob_1");
+        assert_eq!(validate_user_name("Zoë.K").unwrap(), "Zoë.K");
+    }
+
+    #[test]
+    fn invalid_user_name_reports_all_issues() {
+        match validate_user_name("a!") {
+            Err(Error::InvalidUserName(user_name_issues)) => assert_eq!(
+                user_name_issues,
+                vec![
+                    UserNameIssue::TooShort,
+                    UserNameIssue::ContainsForbiddenCharacters
+                ]
+            ),
+            _ => panic!("User name must be invalid"),
+        }
+        assert!(validate_user_name(&"a".repeat(33)).is_err());
+        assert!(validate_user_name("bob smith").is_err());
+    }
+
+    #[test]
+    fn reserved_user_name_is_rejected_regardless_of_case() {
+        assert!(matches!(
+            validate_user_name("Admin"),
+            Err(Error::InvalidUserName(user_name_issues))
+                if user_name_issues == vec![UserNameIssue::Reserved]
+        ));
+    }
+
+    #[test]
+    fn email_is_trimmed_and_checked_roughly() {
+        assert_eq!(
+            validate_email(" bob@example.com ").unwrap(),
+            "bob@example.com"
+        );
+        assert!(validate_email("bob@example").is_err());
+        assert!(validate_email("bob smith@example.com").is_err());
+    }
+
+    #[test]
+    fn account_tokens_are_unique_and_hashed() {
+        let account_token = generate_account_token();
+
+        assert_ne!(account_token, generate_account_token());
+        assert_eq!(hash_account_token(&account_token).len(), 64);
+        assert_ne!(hash_account_token(&account_token), account_token);
+    }
+
+    // Secret of RFC 6238 test vectors, "12345678901234567890" in base32
+    const TEST_TOTP_SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    #[test]
+    fn totp_codes_match_rfc_6238_test_vectors() {
+        assert_eq!(verify_totp_code(TEST_TOTP_SECRET, "287082", 59), Some(1));
+        assert_eq!(
+            verify_totp_code(TEST_TOTP_SECRET, "081804", 1111111109),
+            Some(37037036)
+        );
+        assert_eq!(
+            verify_totp_code(TEST_TOTP_SECRET, "005924", 1234567890),
+            Some(41152263)
+        );
+    }
+
+    #[test]
+    fn totp_code_is_accepted_only_within_allowed_drift() {
+        let step_later = 1234567890 + TOTP_PERIOD_SECONDS;
+        let two_steps_later = 1234567890 + 2 * TOTP_PERIOD_SECONDS;
+
+        assert!(verify_totp_code(TEST_TOTP_SECRET, "005924", step_later).is_some());
+        assert!(verify_totp_code(TEST_TOTP_SECRET, "005924", two_steps_later).is_none());
+        assert!(verify_totp_code(TEST_TOTP_SECRET, "000000", 1234567890).is_none());
+    }
+
+    #[test]
+    fn recovery_code_is_normalized() {
+        let recovery_code = generate_recovery_code();
+
+        assert_eq!(
+            normalize_recovery_code(&recovery_code.to_uppercase()),
+            recovery_code.replace('-', "")
+        );
+    }
+
+    fn generate_test_jwt(token_type: TokenType, valid_for: Duration) -> String {
+        // Tests share one lazily loaded key, so they all set the same secret
+        env::set_var("JWT_SECRET", "test_jwt_secret");
+        generate_jwt(
+            "user_id".to_owned(),
+            "session_id".to_owned(),
+            Uuid::new_v4().to_string(),
+            token_type,
+            Utc::now() + valid_for,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn token_is_accepted_for_its_type() {
+        let access_token = generate_test_jwt(TokenType::Access, Duration::days(1));
+        let refresh_token = generate_test_jwt(TokenType::Refresh, Duration::days(1));
+
+        let claims = decode_jwt(&access_token, TokenType::Access).unwrap();
+        assert_eq!(claims.sub, "user_id");
+        assert_eq!(claims.sid, "session_id");
+        assert!(decode_jwt(&refresh_token, TokenType::Refresh).is_ok());
+    }
+
+    #[test]
+    fn tokens_do_not_outlive_max_session_age() {
+        env::set_var("JWT_SECRET", "test_jwt_secret");
+        let token_lifetimes = get_token_lifetimes(false);
+        let session_created_at =
+            Utc::now() - token_lifetimes.max_session_age + Duration::minutes(5);
+
+        let auth_token = generate_auth_token(
+            "user_id".to_owned(),
+            "session_id".to_owned(),
+            false,
+            session_created_at,
+        )
+        .unwrap();
+
+        let session_expires_at =
+            (session_created_at + token_lifetimes.max_session_age).timestamp() as usize;
+        let access_token_claims = decode_jwt(&auth_token.access_token, TokenType::Access).unwrap();
+        let refresh_token_claims =
+            decode_jwt(&auth_token.refresh_token, TokenType::Refresh).unwrap();
+        assert_eq!(access_token_claims.exp, session_expires_at);
+        assert_eq!(refresh_token_claims.exp, session_expires_at);
+    }
+
+    #[test]
+    fn refresh_token_is_not_accepted_as_access_token() {
+        let refresh_token = generate_test_jwt(TokenType::Refresh, Duration::days(1));
+
+        assert!(decode_jwt(&refresh_token, TokenType::Access).is_err());
+    }
+
+    #[test]
+    fn access_token_is_not_accepted_as_refresh_token() {
+        let access_token = generate_test_jwt(TokenType::Access, Duration::days(1));
+
+        assert!(decode_jwt(&access_token, TokenType::Refresh).is_err());
+    }
+
+    #[test]
+    fn expired_token_is_accepted_only_within_leeway() {
+        let recently_expired_token = generate_test_jwt(TokenType::Access, Duration::seconds(-30));
+        let expired_token = generate_test_jwt(TokenType::Access, Duration::seconds(-120));
+
+        assert!(decode_jwt(&recently_expired_token, TokenType::Access).is_ok());
+        assert!(matches!(
+            decode_jwt(&expired_token, TokenType::Access),
+            Err(Error::JWTTokenExpired)
+        ));
+    }
 }
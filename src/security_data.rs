@@ -1,3 +1,6 @@
+use super::error_data::Error;
+use actix_web::{dev::Payload, FromRequest, HttpRequest};
+use chrono::Duration;
 use diesel::{
     deserialize,
     pg::Pg,
@@ -6,9 +9,22 @@ use diesel::{
     types::{FromSql, IsNull, ToSql},
     AsExpression, FromSqlRow,
 };
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey};
 use serde::{Deserialize, Serialize};
-use std::io::Write;
+use sha1::{Digest, Sha1};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    future::{ready, Ready},
+    io::{ErrorKind, Write},
+    path::PathBuf,
+    sync::Arc,
+};
+
+// Range files are named by this many first hex symbols of SHA-1 digest
+const SHA1_RANGE_PREFIX_LENGTH: usize = 5;
 
+#[derive(Debug, PartialEq)]
 pub enum PasswordStrengthIssue {
     ContainsWhitespace,
     TooShort,
@@ -18,7 +34,111 @@ pub enum PasswordStrengthIssue {
     ContainsNoDigit,
     ContainsNoSymbol,
     SymbolUniquenessRequirementsViolation,
-    None,
+    Compromised,
+}
+
+impl PasswordStrengthIssue {
+    pub fn code(&self) -> &'static str {
+        match self {
+            PasswordStrengthIssue::ContainsWhitespace => "contains_whitespace",
+            PasswordStrengthIssue::TooShort => "too_short",
+            PasswordStrengthIssue::TooLong => "too_long",
+            PasswordStrengthIssue::ContainsNoUpperCaseLetter => "contains_no_upper_case_letter",
+            PasswordStrengthIssue::ContainsNoLowerCaseLetter => "contains_no_lower_case_letter",
+            PasswordStrengthIssue::ContainsNoDigit => "contains_no_digit",
+            PasswordStrengthIssue::ContainsNoSymbol => "contains_no_symbol",
+            PasswordStrengthIssue::SymbolUniquenessRequirementsViolation => {
+                "symbol_uniqueness_requirements_violation"
+            }
+            PasswordStrengthIssue::Compromised => "compromised",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum UserNameIssue {
+    TooShort,
+    TooLong,
+    ContainsForbiddenCharacters,
+    Reserved,
+}
+
+impl UserNameIssue {
+    pub fn code(&self) -> &'static str {
+        match self {
+            UserNameIssue::TooShort => "too_short",
+            UserNameIssue::TooLong => "too_long",
+            UserNameIssue::ContainsForbiddenCharacters => "contains_forbidden_characters",
+            UserNameIssue::Reserved => "reserved",
+        }
+    }
+}
+
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub max_length: usize,
+    pub require_upper_case_letter: bool,
+    pub require_lower_case_letter: bool,
+    pub require_digit: bool,
+    pub require_symbol: bool,
+    // Password must have at least one unique symbol for each this amount of symbols
+    pub symbols_per_unique_symbol: usize,
+    pub denylist: Option<PasswordDenylist>,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        PasswordPolicy {
+            min_length: 8,
+            max_length: 16,
+            require_upper_case_letter: true,
+            require_lower_case_letter: true,
+            require_digit: true,
+            require_symbol: true,
+            symbols_per_unique_symbol: 4,
+            denylist: None,
+        }
+    }
+}
+
+// Passwords, that are known to be breached or too common
+pub enum PasswordDenylist {
+    // Lowercased passwords, one per line in source file
+    PlainText(HashSet<String>),
+    // Directory of k-anonymity range files, like Have I Been Pwned ones. Every file is named by
+    // the prefix of SHA-1 digests, e.g. "5BAA6.txt", and lists the rest of each hex encoded digest
+    // per line, optionally followed by ":<occurrences count>".
+    // Only range of password's prefix is read from disk, so denylist of any size isn't kept in memory
+    Sha1Range(PathBuf),
+}
+
+impl PasswordDenylist {
+    pub fn contains(&self, password: &str) -> bool {
+        match self {
+            PasswordDenylist::PlainText(passwords) => passwords.contains(&password.to_lowercase()),
+            PasswordDenylist::Sha1Range(range_directory_path) => {
+                let password_digest = format!("{:X}", Sha1::digest(password.as_bytes()));
+                let (prefix, suffix) = password_digest.split_at(SHA1_RANGE_PREFIX_LENGTH);
+
+                // Missing range file means, that no digest has this prefix
+                match fs::read_to_string(range_directory_path.join(format!("{}.txt", prefix))) {
+                    Ok(range) => range.lines().any(|line| {
+                        line.split(':')
+                            .next()
+                            .is_some_and(|entry| entry.trim().eq_ignore_ascii_case(suffix))
+                    }),
+                    Err(error) if error.kind() == ErrorKind::NotFound => false,
+                    Err(error) => {
+                        println!(
+                            "Failed to read password denylist range {}: {}",
+                            prefix, error
+                        );
+                        false
+                    }
+                }
+            }
+        }
+    }
 }
 
 #[derive(AsExpression, Debug, FromSqlRow)]
@@ -45,6 +165,55 @@ impl FromSql<Text, Pg> for HashAlgorithm {
     }
 }
 
+#[derive(AsExpression, Debug, FromSqlRow, PartialEq)]
+#[sql_type = "Text"]
+pub enum AccountTokenPurpose {
+    EmailVerification,
+    PasswordReset,
+}
+
+impl ToSql<Text, Pg> for AccountTokenPurpose {
+    fn to_sql<W: Write>(&self, out: &mut Output<W, Pg>) -> serialize::Result {
+        match *self {
+            AccountTokenPurpose::EmailVerification => out.write_all(b"email_verification")?,
+            AccountTokenPurpose::PasswordReset => out.write_all(b"password_reset")?,
+        }
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Text, Pg> for AccountTokenPurpose {
+    fn from_sql(bytes: Option<&[u8]>) -> deserialize::Result<Self> {
+        match not_none!(bytes) {
+            b"email_verification" => Ok(AccountTokenPurpose::EmailVerification),
+            b"password_reset" => Ok(AccountTokenPurpose::PasswordReset),
+            _ => Err("Unrecognized enum variant".into()),
+        }
+    }
+}
+
+// Key, that access and refresh tokens are signed with
+pub struct JwtKey {
+    pub key_id: String,
+    pub algorithm: Algorithm,
+    // Retired keys might have no private part, they are only used for verification
+    pub encoding_key: Option<EncodingKey>,
+    pub decoding_key: DecodingKey,
+    // Only asymmetric keys have public part, that can be published
+    pub public_key: Option<JwtPublicKey>,
+}
+
+// All keys, that tokens are verified with, selected by kid header
+pub struct JwtKeyRing {
+    pub active_key: Arc<JwtKey>,
+    pub keys: HashMap<String, Arc<JwtKey>>,
+}
+
+pub enum JwtPublicKey {
+    Rsa { modulus: Vec<u8>, exponent: Vec<u8> },
+    Ed25519 { public_key: Vec<u8> },
+}
+
 pub struct HashData {
     pub hash: String,
     pub salt: String,
@@ -53,12 +222,110 @@ pub struct HashData {
 
 #[derive(Deserialize, Serialize)]
 pub struct Claims {
+    pub iss: String,
+    pub aud: String,
     pub sub: String,
+    // Id of session, that token belongs to, so that token stops working once session is revoked
+    pub sid: String,
     pub exp: usize,
+    pub nbf: usize,
     pub iat: usize,
+    // Makes every token unique, even if it was issued at the same second for the same user,
+    // also identifies session's current refresh token
+    pub jti: String,
+    // Access and refresh tokens are signed with the same key, so they can't be told apart otherwise
+    pub typ: TokenType,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum TokenType {
+    #[serde(rename = "access")]
+    Access,
+    #[serde(rename = "refresh")]
+    Refresh,
+    // Proves, that password was checked, while sign in waits for second factor
+    #[serde(rename = "mfa_challenge")]
+    MfaChallenge,
+}
+
+#[derive(Clone, Copy)]
+pub struct TokenLifetimes {
+    pub access_token_lifetime: Duration,
+    // Every refresh issues refresh token valid for this long, so active sessions slide forward
+    pub refresh_token_lifetime: Duration,
+    // Session can't be refreshed past this age, no matter how active it is
+    pub max_session_age: Duration,
+}
+
+impl Default for TokenLifetimes {
+    fn default() -> Self {
+        TokenLifetimes {
+            access_token_lifetime: Duration::minutes(15),
+            refresh_token_lifetime: Duration::days(30),
+            max_session_age: Duration::days(90),
+        }
+    }
+}
+
+pub struct TokenLifetimePolicy {
+    pub registered_user: TokenLifetimes,
+    pub guest: TokenLifetimes,
+}
+
+pub struct JwtClaimsPolicy {
+    pub issuer: String,
+    pub audience: String,
+    // Allowed clock skew between servers, that issue and verify tokens
+    pub leeway_seconds: u64,
+}
+
+impl Default for JwtClaimsPolicy {
+    fn default() -> Self {
+        JwtClaimsPolicy {
+            issuer: "machine_notes_server".to_owned(),
+            audience: "machine_notes".to_owned(),
+            leeway_seconds: 60,
+        }
+    }
+}
+
+// Caller of request, whose access token was verified by middleware
+#[derive(Clone)]
+pub struct AuthenticatedUser {
+    pub user_id: String,
+    pub session_id: String,
+}
+
+impl From<Claims> for AuthenticatedUser {
+    fn from(claims: Claims) -> Self {
+        AuthenticatedUser {
+            user_id: claims.sub,
+            session_id: claims.sid,
+        }
+    }
+}
+
+// Middleware puts AuthenticatedUser into request extensions, so handlers don't read headers.
+// It is missing only on public paths, that's why Option<AuthenticatedUser> is extracted there.
+impl FromRequest for AuthenticatedUser {
+    type Error = Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+    type Config = ();
+
+    fn from_request(request: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(
+            request
+                .extensions()
+                .get::<AuthenticatedUser>()
+                .cloned()
+                .ok_or(Error::MissingAccessToken),
+        )
+    }
 }
 
 pub struct AuthToken {
     pub access_token: String,
     pub refresh_token: String,
+    // jti claim of refresh token, that session's refresh token is looked up by
+    pub refresh_token_id: String,
 }
@@ -0,0 +1,65 @@
+use super::utils::get_env_var_or_default;
+use dotenv::dotenv;
+use lazy_static::lazy_static;
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+// Cache is cleaned from outdated entries once it grows over this size
+const SESSION_CACHE_CLEANUP_SIZE: usize = 10_000;
+
+lazy_static! {
+    static ref SESSION_CACHE: Mutex<HashMap<String, CachedSessionState>> =
+        Mutex::new(HashMap::new());
+    static ref SESSION_CACHE_TTL: Duration = get_session_cache_ttl();
+}
+
+struct CachedSessionState {
+    is_active: bool,
+    cached_at: Instant,
+}
+
+// Returns None, if session state is unknown or outdated and has to be loaded from database
+pub fn is_session_active(session_id: &str) -> Option<bool> {
+    let session_cache = SESSION_CACHE.lock().unwrap();
+
+    session_cache
+        .get(session_id)
+        .filter(|cached_session_state| {
+            cached_session_state.cached_at.elapsed() < *SESSION_CACHE_TTL
+        })
+        .map(|cached_session_state| cached_session_state.is_active)
+}
+
+pub fn put_session_state(session_id: String, is_active: bool) {
+    let mut session_cache = SESSION_CACHE.lock().unwrap();
+
+    if session_cache.len() >= SESSION_CACHE_CLEANUP_SIZE {
+        session_cache.retain(|_, cached_session_state| {
+            cached_session_state.cached_at.elapsed() < *SESSION_CACHE_TTL
+        });
+    }
+
+    session_cache.insert(
+        session_id,
+        CachedSessionState {
+            is_active,
+            cached_at: Instant::now(),
+        },
+    );
+}
+
+// Revoked sessions are rejected by this server instance immediately,
+// other instances notice revocation once their cached state becomes outdated
+pub fn revoke_sessions(session_ids: Vec<String>) {
+    for session_id in session_ids {
+        put_session_state(session_id, false);
+    }
+}
+
+fn get_session_cache_ttl() -> Duration {
+    dotenv().ok();
+    Duration::from_secs(get_env_var_or_default("SESSION_CACHE_TTL_SECONDS", 60))
+}
@@ -0,0 +1,233 @@
+use super::{
+    error_data::Error,
+    postgres_database_connection::{establish_database_connection, PostgresDatabaseConnectionPool},
+    schema::{consumed_refresh_token, session},
+    session_entity::{ConsumedRefreshTokenEntity, InsertableSessionEntity, SessionEntity},
+};
+use diesel::{
+    dsl::exists,
+    prelude::*,
+    result::{DatabaseErrorKind, Error as DieselError},
+};
+use std::{sync::Arc, time::SystemTime};
+
+pub async fn insert_session(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    insertable_session_entity: InsertableSessionEntity,
+) -> Result<SessionEntity, Error> {
+    let database_connection = establish_database_connection(database_connection_pool)?;
+
+    let session_entity: SessionEntity = diesel::insert_into(session::table)
+        .values(&insertable_session_entity)
+        .get_result(&database_connection)?;
+
+    println!("Sucessfully inserted session {}", session_entity);
+
+    Ok(session_entity)
+}
+
+pub async fn get_session(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    session_id: String,
+) -> Result<SessionEntity, Error> {
+    let database_connection = establish_database_connection(database_connection_pool)?;
+
+    let session_entity: SessionEntity = session::table
+        .find(session_id)
+        .first(&database_connection)
+        .map_err(map_not_found_error)?;
+
+    println!("Sucessfully loaded session {}", session_entity);
+
+    Ok(session_entity)
+}
+
+pub async fn is_session_active(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    session_id: String,
+) -> Result<bool, Error> {
+    let database_connection = establish_database_connection(database_connection_pool)?;
+
+    let is_session_active =
+        diesel::select(exists(session::table.find(session_id))).get_result(&database_connection)?;
+
+    Ok(is_session_active)
+}
+
+pub async fn get_sessions(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    user_id: String,
+) -> Result<Vec<SessionEntity>, Error> {
+    let database_connection = establish_database_connection(database_connection_pool)?;
+
+    let session_entities: Vec<SessionEntity> = session::table
+        .filter(session::user_id.eq(user_id))
+        .order(session::last_used_at.desc())
+        .load(&database_connection)?;
+
+    println!("Sucessfully loaded {} sessions", session_entities.len());
+
+    Ok(session_entities)
+}
+
+// Expired sessions are deleted, so that they don't pile up
+pub async fn delete_expired_sessions(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    user_id: String,
+    created_before: SystemTime,
+    last_used_before: SystemTime,
+) -> Result<Vec<String>, Error> {
+    let database_connection = establish_database_connection(database_connection_pool)?;
+
+    let delete_source = session::table.filter(session::user_id.eq(user_id)).filter(
+        session::created_at
+            .lt(created_before)
+            .or(session::last_used_at.lt(last_used_before)),
+    );
+    let deleted_session_ids: Vec<String> = diesel::delete(delete_source)
+        .returning(session::session_id)
+        .get_results(&database_connection)?;
+
+    println!("Deleted {} expired sessions", deleted_session_ids.len());
+
+    Ok(deleted_session_ids)
+}
+
+pub async fn get_consumed_refresh_token(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    refresh_token_id: String,
+) -> Result<Option<ConsumedRefreshTokenEntity>, Error> {
+    let database_connection = establish_database_connection(database_connection_pool)?;
+
+    let consumed_refresh_token_entity = consumed_refresh_token::table
+        .find(refresh_token_id)
+        .first(&database_connection)
+        .optional()?;
+
+    Ok(consumed_refresh_token_entity)
+}
+
+// Replaces session's refresh token with new one and marks old one as consumed.
+// Fails with RefreshTokenReused, if refresh token was consumed concurrently.
+pub async fn rotate_refresh_token(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    consumed_refresh_token_entity: ConsumedRefreshTokenEntity,
+    refresh_token_hash: String,
+    refresh_token_id: String,
+) -> Result<SessionEntity, Error> {
+    let database_connection = establish_database_connection(database_connection_pool)?;
+
+    let session_entity: SessionEntity = database_connection
+        .transaction::<_, DieselError, _>(|| {
+            diesel::insert_into(consumed_refresh_token::table)
+                .values(&consumed_refresh_token_entity)
+                .execute(&database_connection)?;
+
+            let source = session::table
+                .find(&consumed_refresh_token_entity.session_id)
+                .filter(
+                    session::refresh_token_id.eq(&consumed_refresh_token_entity.refresh_token_id),
+                );
+            diesel::update(source)
+                .set((
+                    (session::refresh_token_hash.eq(refresh_token_hash)),
+                    (session::refresh_token_id.eq(refresh_token_id)),
+                    (session::last_used_at.eq(SystemTime::now())),
+                ))
+                .get_result(&database_connection)
+        })
+        .map_err(|error| match error {
+            DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                Error::RefreshTokenReused
+            }
+            DieselError::NotFound => Error::WrongRefreshToken,
+            _ => Error::from(error),
+        })?;
+
+    println!(
+        "Sucessfully rotated session {}'s refresh token",
+        session_entity
+    );
+
+    Ok(session_entity)
+}
+
+// Consumed refresh tokens of session are deleted along with it
+pub async fn delete_session(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    session_id: String,
+) -> Result<usize, Error> {
+    let database_connection = establish_database_connection(database_connection_pool)?;
+
+    let num_deleted =
+        diesel::delete(session::table.find(session_id)).execute(&database_connection)?;
+
+    println!("Deleted {} sessions", num_deleted);
+
+    Ok(num_deleted)
+}
+
+// Deletes session only if it belongs to user, so that users can't revoke each other's sessions
+pub async fn delete_user_session(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    user_id: String,
+    session_id: String,
+) -> Result<usize, Error> {
+    let database_connection = establish_database_connection(database_connection_pool)?;
+
+    let delete_source = session::table
+        .find(session_id)
+        .filter(session::user_id.eq(user_id));
+    let num_deleted = diesel::delete(delete_source).execute(&database_connection)?;
+
+    println!("Deleted {} sessions", num_deleted);
+
+    if num_deleted == 0 {
+        Err(Error::SessionNotFound)
+    } else {
+        Ok(num_deleted)
+    }
+}
+
+// Returns ids of deleted sessions
+pub async fn delete_sessions(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    user_id: String,
+) -> Result<Vec<String>, Error> {
+    let database_connection = establish_database_connection(database_connection_pool)?;
+
+    let delete_source = session::table.filter(session::user_id.eq(user_id));
+    let deleted_session_ids: Vec<String> = diesel::delete(delete_source)
+        .returning(session::session_id)
+        .get_results(&database_connection)?;
+
+    println!("Deleted {} sessions", deleted_session_ids.len());
+
+    Ok(deleted_session_ids)
+}
+
+pub async fn delete_other_sessions(
+    database_connection_pool: Arc<PostgresDatabaseConnectionPool>,
+    user_id: String,
+    current_session_id: String,
+) -> Result<Vec<String>, Error> {
+    let database_connection = establish_database_connection(database_connection_pool)?;
+
+    let delete_source = session::table
+        .filter(session::user_id.eq(user_id))
+        .filter(session::session_id.ne(current_session_id));
+    let deleted_session_ids: Vec<String> = diesel::delete(delete_source)
+        .returning(session::session_id)
+        .get_results(&database_connection)?;
+
+    println!("Deleted {} sessions", deleted_session_ids.len());
+
+    Ok(deleted_session_ids)
+}
+
+fn map_not_found_error(error: DieselError) -> Error {
+    match error {
+        DieselError::NotFound => Error::SessionRevoked,
+        _ => Error::from(error),
+    }
+}
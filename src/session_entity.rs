@@ -0,0 +1,82 @@
+use super::{
+    error_data::Error,
+    schema::{consumed_refresh_token, session},
+    security,
+    security_data::AuthToken,
+};
+use diesel::{Insertable, Queryable};
+use std::{
+    fmt::{Display, Formatter},
+    time::SystemTime,
+};
+use uuid::Uuid;
+
+#[derive(Insertable)]
+#[table_name = "session"]
+pub struct InsertableSessionEntity {
+    pub session_id: String,
+    pub user_id: String,
+    pub device_name: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: SystemTime,
+    pub refresh_token_hash: String,
+    pub refresh_token_id: String,
+}
+
+impl InsertableSessionEntity {
+    // Only keyed hash of refresh token is stored, so raw auth token is returned along with entity
+    pub fn new(
+        user_id: String,
+        is_guest: bool,
+        device_name: Option<String>,
+        user_agent: Option<String>,
+    ) -> Result<(Self, AuthToken), Error> {
+        let session_id = Uuid::new_v4().to_string();
+        // Creation time is set here rather than by database, so tokens can't outlive max session age
+        let created_at = SystemTime::now();
+        let auth_token = security::generate_auth_token(
+            user_id.clone(),
+            session_id.clone(),
+            is_guest,
+            created_at.into(),
+        )?;
+
+        let insertable_session_entity = InsertableSessionEntity {
+            session_id,
+            user_id,
+            device_name,
+            user_agent,
+            created_at,
+            refresh_token_hash: security::hash_refresh_token(&auth_token.refresh_token),
+            refresh_token_id: auth_token.refresh_token_id.clone(),
+        };
+
+        Ok((insertable_session_entity, auth_token))
+    }
+}
+
+#[derive(Queryable)]
+pub struct SessionEntity {
+    pub session_id: String,
+    pub user_id: String,
+    pub device_name: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: SystemTime,
+    pub last_used_at: SystemTime,
+    pub refresh_token_hash: String,
+    pub refresh_token_id: String,
+}
+
+impl Display for SessionEntity {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.session_id)
+    }
+}
+
+#[derive(Insertable, Queryable)]
+#[table_name = "consumed_refresh_token"]
+pub struct ConsumedRefreshTokenEntity {
+    pub refresh_token_id: String,
+    pub session_id: String,
+    pub consumed_at: SystemTime,
+}
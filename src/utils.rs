@@ -1,9 +1,32 @@
-use regex::Regex;
 use lazy_static::lazy_static;
+use regex::Regex;
+use std::{env, str::FromStr};
 
 lazy_static! {
     pub static ref UPPER_CASE_LETTER_REGEX: Regex = Regex::new("[[:upper:]]").unwrap();
     pub static ref LOWER_CASE_LETTER_REGEX: Regex = Regex::new("[[:lower:]]").unwrap();
     pub static ref DIGITS_REGEX: Regex = Regex::new("[[:digit:]]").unwrap();
     pub static ref SYMBOLS_REGEX: Regex = Regex::new("[[:punct:]]").unwrap();
+    pub static ref EMAIL_REGEX: Regex = Regex::new(r"^[^@\s]+@[^@\s]+\.[^@\s]+$").unwrap();
+    pub static ref USER_NAME_REGEX: Regex = Regex::new(r"^[\p{L}\p{M}\p{N}_.-]*$").unwrap();
+}
+
+pub fn get_env_var_or_default<T: FromStr>(key: &str, default: T) -> T {
+    match env::var(key) {
+        Ok(value) => value
+            .parse()
+            .unwrap_or_else(|_| panic!("{} has invalid value {}", key, value)),
+        Err(_) => default,
+    }
+}
+
+pub fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(&hex[index..index + 2], 16).ok())
+        .collect()
 }